@@ -7,6 +7,8 @@ import collections
 
 
 ENTRY_STRUCT = struct.Struct(">QHHI")
+# key, move, wins, draws, losses, elo, year of the file of rschessbook make-book -stats
+STATS_STRUCT = struct.Struct(">QHIIIHH")
 
 
 class Uci(collections.namedtuple("Entry", ["key", "raw_move", "weight", "learn"])):
@@ -535,6 +537,54 @@ class ClaveHash:
 
 
         
+class StatsReader(object):
+    """Las estadisticas de las jugadas escritas por make-book -stats"""
+
+    def __init__(self, filename):
+        with open(filename, "rb") as f:
+            self.data = f.read()
+
+    def __len__(self):
+        return len(self.data) // STATS_STRUCT.size
+
+    def find(self, key, raw_move):
+        """(wins, draws, losses, elo, year) de la jugada, None si no esta."""
+        lo = 0
+        hi = len(self)
+        while lo < hi:
+            mid = (lo + hi) // 2
+            mid_key = STATS_STRUCT.unpack_from(self.data, mid * STATS_STRUCT.size)[0]
+            if mid_key < key:
+                lo = mid + 1
+            else:
+                hi = mid
+
+        while lo < len(self):
+            entry = STATS_STRUCT.unpack_from(self.data, lo * STATS_STRUCT.size)
+            if entry[0] != key:
+                break
+            if entry[1] == raw_move:
+                return entry[2:]
+            lo += 1
+        return None
+
+
+def lee_metadatos(libro_bin):
+    """Los pares clave=valor del fichero libro.bin.meta de make-book."""
+    metadatos = {}
+    try:
+        with open(libro_bin + ".meta") as f:
+            for linea in f:
+                linea = linea.rstrip("\n")
+                if linea.startswith("#") or "=" not in linea:
+                    continue
+                clave, valor = linea.split("=", 1)
+                metadatos[clave] = valor
+    except OSError:
+        pass
+    return metadatos
+
+
 def open_reader(path):
     return MemoryMappedReader(path)
     
@@ -545,20 +595,46 @@ def busca_clave(v_fen):
     return clave.hash(v_fen)
 
 
-def busca_libro(libro_bin, v_fen):
+def busca_libro(libro_bin, v_fen, fichero_stats=None):
     total_weight = 0
     lista_jugadas = []
     #clave = ClaveHash()
     key = busca_clave(v_fen)    #clave.hash(v_fen)
     # print("linea 558", key)
+
+    # las estadisticas del fichero de -stats o del campo learn con -learn wdl
+    metadatos = lee_metadatos(libro_bin)
+    if fichero_stats is None:
+        fichero_stats = metadatos.get("stats")
+    stats = None
+    if fichero_stats is not None and os.path.exists(fichero_stats):
+        stats = StatsReader(fichero_stats)
+    learn_wdl = metadatos.get("learn") == "wdl"
+
     with open_reader(libro_bin) as reader:
         for entry in reader.find_all(key):
             total_weight += entry.weight
-            lista_jugadas.append({'movim':entry.move(), 'peso': entry.weight})
+            estadisticas = None
+            if stats is not None:
+                estadisticas = stats.find(entry.key, entry.raw_move)
+            elif learn_wdl:
+                estadisticas = ((entry.learn >> 20) & 1023, (entry.learn >> 10) & 1023, entry.learn & 1023)
+            lista_jugadas.append({'movim':entry.move(), 'peso': entry.weight, 'stats': estadisticas})
             #  print(entry.move(), entry.weight, entry.learn)
             
     for entrada in lista_jugadas:
-        print("%s --> %.2f %%" % (entrada['movim'], (entrada['peso'] * 100)/total_weight))
+        linea = "%s --> %.2f %%" % (entrada['movim'], (entrada['peso'] * 100)/total_weight)
+        estadisticas = entrada['stats']
+        if estadisticas is not None:
+            wins, draws, losses = estadisticas[:3]
+            linea += "   +%d =%d -%d" % (wins, draws, losses)
+            if len(estadisticas) > 3:
+                elo, year = estadisticas[3:]
+                if elo:
+                    linea += "  elo %d" % elo
+                if year:
+                    linea += "  %d" % year
+        print(linea)
     
     
 if __name__ == '__main__':
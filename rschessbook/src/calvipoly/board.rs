@@ -6,6 +6,24 @@ use super::util;
 use super::zobrist as zob;
 
 
+/* State of the game in the current position */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GameStatus {
+    Ongoing,
+    Checkmate,
+    Stalemate,
+    ThreefoldRepetition,
+    FiftyMoves,
+    InsufficientMaterial,
+}
+
+impl GameStatus {
+    pub fn is_over(&self) -> bool {
+        *self != GameStatus::Ongoing
+    }
+}
+
+
 /* Board representation */
 #[derive(Clone)]
 pub struct Sboard {
@@ -169,8 +187,13 @@ impl Sboard {
 
     pub fn set_fen(&mut self, fen: &str) -> bool {
 	
-        assert!(!fen.is_empty());
-        
+        // the six fields, separated by one space
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if !valid_fen_fields(&fields) {
+            return false;
+        }
+        let fen = fields.join(" ");
+
         let mut rank: i16 = util::RANK_MIN as i16;
         let mut file: i16 = util::FILE_MIN as i16;
         let mut piece: usize;
@@ -179,11 +202,11 @@ impl Sboard {
         let mut j = 0usize; 
         let mut v_sq64: usize;
         let mut curr_char: &str;    // current char into the fen
-        let trim_fen = fen.trim();
+        let trim_fen = fen.as_str();
     
         self.reset_board();
     
-        while (rank <= util::RANK_MAX as i16) && j < fen.len() {
+        while (rank <= util::RANK_MAX as i16) && j < trim_fen.len() {
             count = 1;
             curr_char = &trim_fen[j..j+1];
 
@@ -300,17 +323,14 @@ impl Sboard {
             match opt {
                 Some(idx) => {
                     // the range of valid en passant squares a6-h6 and a3-h3
-                    let col = util::get_col(idx);
                     match idx {
                         16..=23 => {
                             self.en_passant = idx as i16;
                             self.piece[idx] = square::EPS_SQUARE;
-                            tmp_hash ^= zob::RANDOM_EN_PASSANT[col];
                         },
                         40..=47 => {
                             self.en_passant = idx as i16;
                             self.piece[idx] = square::EPS_SQUARE;
-                            tmp_hash ^= zob::RANDOM_EN_PASSANT[col];
                         },
                         _ => {
                             eprintln!("no valid range in ep-square : {}", curr_char);
@@ -334,12 +354,20 @@ impl Sboard {
         let plies: Vec<&str> = split.collect();
         if plies.len() != 2 {return false;}
 
-        self.ply_pawn = plies[0].parse::<usize>().unwrap();
-        self.ply = plies[1].parse::<usize>().unwrap();
+        match (plies[0].parse::<usize>(), plies[1].parse::<usize>()) {
+            (Ok(ply_pawn), Ok(ply)) => {
+                self.ply_pawn = ply_pawn;
+                self.ply = ply;
+            }
+            _ => return false,
+        }
 
         self.hash_key = tmp_hash;
-let mut clon = self.clone();
-assert_eq!(self.hash_key, zob::hash_key(&mut clon));
+        // polyglot only hashes the en passant square if a pawn can capture
+        if self.en_passant != -1 {
+            self.hash_key = zob::hash_key(&mut *self);
+        }
+        debug_assert_eq!(self.hash_key, zob::hash_key(&mut self.clone()));
         true
     }
 
@@ -576,6 +604,104 @@ assert_eq!(self.hash_key, zob::hash_key(&mut clon));
     }
 
 
+    // ============================================
+    // relative to the state of the game ...
+    // ============================================
+
+    /* Returns the state of the game in the current position.
+    *  Checkmate and stalemate are checked first, then the draws */
+    pub fn status(&mut self) -> GameStatus {
+
+        if !self.has_legal_moves() {
+            if self.in_check() {
+                return GameStatus::Checkmate;
+            }
+            return GameStatus::Stalemate;
+        }
+        if self.insufficient_material() {
+            return GameStatus::InsufficientMaterial;
+        }
+        if self.ply_pawn >= 100 {
+            return GameStatus::FiftyMoves;
+        }
+        if self.repetitions() >= 3 {
+            return GameStatus::ThreefoldRepetition;
+        }
+
+        GameStatus::Ongoing
+    }
+
+
+    /* Is the side to move in check? */
+    pub fn in_check(&mut self) -> bool {
+        let side = self.side;
+        self.is_in_check(side)
+    }
+
+
+    /* Has the side to move at least one legal move? */
+    pub fn has_legal_moves(&mut self) -> bool {
+        let mut moves: Vec<mv::Smove> = Vec::new();
+        self.gen_moves(&mut moves);
+
+        for m in moves.iter_mut() {
+            let legal = self.make_move(m).is_some();
+            self.undo_move(m);
+            if legal {
+                return true;
+            }
+        }
+        false
+    }
+
+
+    /* Number of times the current position has appeared, counting itself.
+    *  Only the moves since the last pawn move or capture can repeat it */
+    pub fn repetitions(&mut self) -> usize {
+        let mut count: usize = 1;
+        let last = self.hist.len() - 1;     // hist[0] is an empty move
+        let reversible = if self.ply_pawn < last { self.ply_pawn } else { last };
+
+        for k in 0..reversible {
+            if self.hist[last - k].hash == self.hash_key {
+                count += 1;
+            }
+        }
+        count
+    }
+
+
+    /* No sequence of legal moves can lead to checkmate:
+    *  K v K, K+minor v K, or only bishops all on squares of the same colour */
+    pub fn insufficient_material(&mut self) -> bool {
+        let mut knights: usize = 0;
+        let mut bishops_light: usize = 0;
+        let mut bishops_dark: usize = 0;
+
+        for i in 0..64 {
+            match self.piece[i] {
+                pi::PAWN | pi::ROOK | pi::QUEEN => return false,
+                pi::KNIGHT => knights += 1,
+                pi::BISHOP => {
+                    if (util::get_row(i) + util::get_col(i)) & 1 == 0 {
+                        bishops_light += 1;
+                    }
+                    else {
+                        bishops_dark += 1;
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        let minors = knights + bishops_light + bishops_dark;
+        if minors <= 1 {
+            return true;
+        }
+        knights == 0 && (bishops_light == 0 || bishops_dark == 0)
+    }
+
+
 
     // ============================================
     // relative to generation of legal moves ...
@@ -1419,13 +1545,32 @@ assert_eq!(self.hash_key, zob::hash_key(&mut clon));
         self.hist[self.hdp].m = m.clone();
         self.hist[self.hdp].cap = self.piece[m.dest];	/* store in history the piece of the dest square */
         self.hist[self.hdp].castle = self.castle_rights;
+        self.hist[self.hdp].ply_pawn = self.ply_pawn;
+        self.hist[self.hdp].passant = self.en_passant;
+        self.hist[self.hdp].hash = self.hash_key;
+
+        /* A null move (-- or Z0 in PGN) only gives the turn to the opponent */
+        if m.tipe == mv::MOVE_TYPE_NONE {
+            self.hist[self.hdp].cap = square::EMPTY;
+            if self.en_passant != -1 {
+                self.piece[self.en_passant as usize] = square::EMPTY;
+                self.en_passant = -1;
+            }
+            self.ply += 1;
+            self.ply_pawn += 1;
+            self.side = (color::WHITE + color::BLACK) - self.side;
+            self.hash_key = zob::hash_key(&mut *self);
+            return Some(m.clone());
+        }
 
         self.piece[m.dest] = self.piece[m.from];	/* dest piece is the one in the original square */
         self.color[m.dest] = self.color[m.from];	/* The dest square color is the one of the origin piece */
         
 
-        /* ply pawn move */
-        if self.piece[m.from] == pi::PAWN {
+        /* ply pawn move or capture */
+        if self.piece[m.from] == pi::PAWN || (
+                self.hist[self.hdp].cap != square::EMPTY && 
+                self.hist[self.hdp].cap != square::EPS_SQUARE) {
             self.ply_pawn = 0;
         }
         else {
@@ -1448,9 +1593,10 @@ assert_eq!(self.hash_key, zob::hash_key(&mut clon));
             self.en_passant = -1;
         }
 
-        /* Remove possible eps piece, remaining from former move */
+        /* Remove possible eps piece, remaining from former move (or from the fen) */
         //if self.hdp > 0 {
-            if self.hist[self.hdp - 1].m.tipe == mv::MOVE_TYPE_PAWN_TWO {
+            if self.hist[self.hdp - 1].m.tipe == mv::MOVE_TYPE_PAWN_TWO || 
+                    self.en_passant != -1 {
                 for i in 16..=23 {   //(i = 16; i <= 23; i++) {
                     if self.piece[i] == square::EPS_SQUARE {
                         self.piece[i] = square::EMPTY;
@@ -1471,6 +1617,8 @@ assert_eq!(self.hash_key, zob::hash_key(&mut clon));
                 }
             }
         //}
+        /* the en passant right only lasts one move */
+        self.en_passant = -1;
 
         /* Add the eps square when a pawn moves two squares */
         if m.tipe == mv::MOVE_TYPE_PAWN_TWO {
@@ -1589,6 +1737,18 @@ assert_eq!(self.hash_key, zob::hash_key(&mut clon));
         self.hdp = self.hist.len() -1;
         self.ply -= 1;
 
+        /* Undo a null move, nothing was moved */
+        if self.hist[self.hdp].m.tipe == mv::MOVE_TYPE_NONE {
+            self.ply_pawn = self.hist[self.hdp].ply_pawn;
+            self.en_passant = self.hist[self.hdp].passant;
+            self.hash_key = self.hist[self.hdp].hash;
+            if self.en_passant != -1 {
+                self.piece[self.en_passant as usize] = square::EPS_SQUARE;
+            }
+            self.hist.pop();
+            return;
+        }
+
         self.piece[self.hist[self.hdp].m.from] = self.piece[self.hist[self.hdp].m.dest];
         self.piece[self.hist[self.hdp].m.dest] = self.hist[self.hdp].cap;
         self.color[self.hist[self.hdp].m.from] = self.side;
@@ -1694,9 +1854,56 @@ assert_eq!(self.hash_key, zob::hash_key(&mut clon));
             }
         }
         //self.side = side;
+
+        /* Restore the state saved by make_move */
+        self.ply_pawn = self.hist[self.hdp].ply_pawn;
+        self.en_passant = self.hist[self.hdp].passant;
+        self.hash_key = self.hist[self.hdp].hash;
+        if self.en_passant != -1 {
+            self.piece[self.en_passant as usize] = square::EPS_SQUARE;
+        }
     
         //self.ply -= 1;
         self.hist.pop();
         
     }
+}
+
+
+
+// valid_fen_fields()
+// the fields of a fen: 8 ranks of 8 squares, the side to move, the castles,
+// the en passant square and the numbers of half-moves and moves
+
+fn valid_fen_fields(fields: &[&str]) -> bool {
+    if fields.len() != 6 {
+        return false;
+    }
+
+    let ranks: Vec<&str> = fields[0].split('/').collect();
+    let valid_rank = |rank: &&str| -> bool {
+        let mut squares: u32 = 0;
+        for c in rank.chars() {
+            match c {
+                '1'..='8' => squares += c.to_digit(10).unwrap(),
+                'p' | 'r' | 'n' | 'b' | 'q' | 'k' | 'P' | 'R' | 'N' | 'B' | 'Q' | 'K' => squares += 1,
+                _ => return false,
+            }
+        }
+        squares == 8
+    };
+    if ranks.len() != 8 || !ranks.iter().all(valid_rank) {
+        return false;
+    }
+
+    let castles = fields[2];
+    let valid_castles = castles == "-" || (castles.len() <= 4 && 
+        castles.chars().enumerate().all(|(i, c)| "KQkq".contains(c) && !castles[..i].contains(c)));
+
+    let passant = fields[3].as_bytes();
+    let valid_passant = fields[3] == "-" || (passant.len() == 2 && 
+        (b'a'..=b'h').contains(&passant[0]) && (passant[1] == b'3' || passant[1] == b'6'));
+
+    (fields[1] == "w" || fields[1] == "b") && valid_castles && valid_passant && 
+        fields[4].parse::<usize>().is_ok() && fields[5].parse::<usize>().is_ok()
 }
\ No newline at end of file
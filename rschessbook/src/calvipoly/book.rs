@@ -1,7 +1,43 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
 
 
-#[derive(Debug)]
+
+// how the weight of a move is calculated from its games
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WeightFormula {
+    Polyglot,           // 2*wins + draws
+    Frequency,          // the games
+    Score,              // score percentage * percentage of the games of the position
+    Wins,               // wins only
+    ThreeOneZero,       // 3*wins + draws
+    Wilson,             // lower bound of the Wilson interval of the score
+    Uniform,            // all the moves the same
+}
+
+pub const WEIGHT_FORMULAS: [(&str, WeightFormula); 7] = [
+    ("polyglot", WeightFormula::Polyglot),
+    ("frequency", WeightFormula::Frequency),
+    ("score", WeightFormula::Score),
+    ("wins", WeightFormula::Wins),
+    ("3-1-0", WeightFormula::ThreeOneZero),
+    ("wilson", WeightFormula::Wilson),
+    ("uniform", WeightFormula::Uniform),
+];
+
+impl WeightFormula {
+    pub fn from_name(name: &str) -> Option<WeightFormula> {
+        WEIGHT_FORMULAS.iter().find(|(n, _)| *n == name).map(|(_, f)| *f)
+    }
+
+    pub fn name(&self) -> &'static str {
+        WEIGHT_FORMULAS.iter().find(|(_, f)| f == self).map(|(n, _)| *n).unwrap()
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct SfinalEntry {
     pub key   : u64,
     pub move_ : u16, 
@@ -22,17 +58,113 @@ impl SfinalEntry {
 
 
 
+// read_book_file()
+// the entries of a polyglot book, 16 bytes each in big endian 
+// (an incomplete entry at the end is not read)
+
+pub fn read_book_file(bin_file: &str) -> std::io::Result<Vec<SfinalEntry>> {
+    let bytes = std::fs::read(bin_file)?;
+    let number = |b: &[u8]| -> u64 { b.iter().fold(0, |n, &byte| (n << 8) | byte as u64) };
+
+    Ok(bytes.chunks_exact(16).map(|e| {
+        SfinalEntry::new(number(&e[0..8]), number(&e[8..10]) as u16, 
+            number(&e[10..12]) as u16, number(&e[12..16]) as u32)
+    }).collect())
+}
+
+
+// write_book_file()
+// the entries in a polyglot book, in the order of the slice
+
+pub fn write_book_file(bin_file: &str, entries: &[SfinalEntry]) -> std::io::Result<()> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(entries.len() * 16);
+    for entry in entries.iter() {
+        bytes.extend_from_slice(&entry.key.to_be_bytes());
+        bytes.extend_from_slice(&entry.move_.to_be_bytes());
+        bytes.extend_from_slice(&entry.weight.to_be_bytes());
+        bytes.extend_from_slice(&entry.learn.to_be_bytes());
+    }
+    std::fs::write(bin_file, bytes)
+}
+
+
+
+// entries_by_key()
+// the entries of every position, by descending weight and by move
+
+pub fn entries_by_key(entries: &[SfinalEntry]) -> HashMap<u64, Vec<SfinalEntry>> {
+    let mut positions: HashMap<u64, Vec<SfinalEntry>> = HashMap::new();
+    for entry in entries.iter() {
+        positions.entry(entry.key).or_default().push(entry.clone());
+    }
+    for (_key, value) in positions.iter_mut() {
+        value.sort_by(|a, b| b.weight.cmp(&a.weight).then(a.move_.cmp(&b.move_)));
+    }
+    positions
+}
+
+
+
+/* The games of a move, from the side that moves, not weighted; 
+*  elo is the average of the players that have moved it (0 without Elo) 
+*  and year the last one it was played (0 without dates) */
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmoveStats {
+    pub key   : u64,
+    pub move_ : u16,
+    pub wins  : u32,
+    pub draws : u32,
+    pub losses: u32,
+    pub elo   : u16,
+    pub year  : u16,
+}
+
+impl SmoveStats {
+
+    // learn()
+    // the wins, draws and losses packed in the learn field of polyglot: 
+    // wins << 20 | draws << 10 | losses, scaled down together to 10 bits
+
+    pub fn learn(&self) -> u32 {
+        let max = self.wins.max(self.draws).max(self.losses);
+        let scale = |n: u32| -> u32 {
+            if max > 1023 { ((n as u64 * 1023 + max as u64 / 2) / max as u64) as u32 } else { n }
+        };
+        (scale(self.wins) << 20) | (scale(self.draws) << 10) | scale(self.losses)
+    }
+}
+
+
+// unpack_learn()
+// the wins, draws and losses of a learn field made by SmoveStats::learn()
+
+pub fn unpack_learn(learn: u32) -> (u32, u32, u32) {
+    ((learn >> 20) & 1023, (learn >> 10) & 1023, learn & 1023)
+}
+
+
+
+// the bytes of an entry in the file of -update
+pub const DB_ENTRY_SIZE: usize = 66;
+
 #[derive(Debug)]
 pub struct Sentry {
     /* this 4 fields are mandatory */
     pub key   : u64,
     pub move_ : u16, 
-    pub weight: u16,    // 2*(win_white)+(draws);  draws = total_games - win_white - win_black)
-    pub learn : u32,    // not used
-    /* this others are used for calculations */
-    win_white: i32,
-    win_black: i32,
+    pub weight: u16,    // 2*(wins)+(draws) of the side that moves
+    pub learn : u32,    // 0, or the packed results with -learn wdl
+    /* this others are used for calculations, from the side that moves;
+       every game adds its contribution (1 if the games are not weighted) */
+    wins: f64,
+    draws: f64,
+    losses: f64,
     total_games: i32,
+    /* the statistics of the games, without their contributions */
+    results: [u32; 3],      // wins, draws, losses
+    elo_sum: u64,
+    elo_games: u32,
+    last_year: u32,
 }
  
  impl Sentry {
@@ -42,32 +174,98 @@ pub struct Sentry {
             move_: 0,
             weight: 0,
             learn: 0,
-            win_white: 0,
-            win_black: 0,
+            wins: 0.0,
+            draws: 0.0,
+            losses: 0.0,
             total_games: 0,
+            results: [0; 3],
+            elo_sum: 0,
+            elo_games: 0,
+            last_year: 0,
         }
     }
 
-    pub fn create_entry ( key: u64, move_: u16, winned: i32) -> Self {
-        let w_white: i32;
-        let w_black: i32;
+    // resul is 1, 0 or -1 for the side that moves, 
+    // elo the one of the player that moves and year the one of the game (0 if unknown)
+    pub fn create_entry ( key: u64, move_: u16, resul: i32, contribution: f64, elo: i32, year: u32) -> Self {
+        let mut entry = Sentry::new();
+        entry.key = key;
+        entry.move_ = move_;
+        entry.add_game(resul, contribution, elo, year);
+        entry
+    }
 
-        if winned < 0 {
-            w_black = winned;
-            w_white = 0;
+    pub fn add_game (&mut self, resul: i32, contribution: f64, elo: i32, year: u32) {
+        if resul > 0 {
+            self.wins += contribution;
+            self.results[0] += 1;
+        }
+        else if resul < 0 {
+            self.losses += contribution;
+            self.results[2] += 1;
         }
         else {
-            w_black = 0;
-            w_white = winned;
+            self.draws += contribution;
+            self.results[1] += 1;
+        }
+        self.total_games += 1;
+        if elo > 0 {
+            self.elo_sum += elo as u64;
+            self.elo_games += 1;
+        }
+        self.last_year = self.last_year.max(year);
+    }
+
+    // to_bytes()
+    // the entry for the file of -update, DB_ENTRY_SIZE bytes in big endian
+    pub fn to_bytes (&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(DB_ENTRY_SIZE);
+        bytes.extend_from_slice(&self.key.to_be_bytes());
+        bytes.extend_from_slice(&self.move_.to_be_bytes());
+        bytes.extend_from_slice(&self.wins.to_be_bytes());
+        bytes.extend_from_slice(&self.draws.to_be_bytes());
+        bytes.extend_from_slice(&self.losses.to_be_bytes());
+        bytes.extend_from_slice(&self.total_games.to_be_bytes());
+        for n in self.results.iter() {
+            bytes.extend_from_slice(&n.to_be_bytes());
         }
+        bytes.extend_from_slice(&self.elo_sum.to_be_bytes());
+        bytes.extend_from_slice(&self.elo_games.to_be_bytes());
+        bytes.extend_from_slice(&self.last_year.to_be_bytes());
+        bytes
+    }
+
+    // from_bytes()
+    // the entry written by to_bytes()
+    pub fn from_bytes (bytes: &[u8]) -> Self {
+        assert_eq!(bytes.len(), DB_ENTRY_SIZE);
+        let u64_at = |i: usize| u64::from_be_bytes(bytes[i..i+8].try_into().unwrap());
+        let u32_at = |i: usize| u32::from_be_bytes(bytes[i..i+4].try_into().unwrap());
         Sentry {
-            key    : key,
-            move_  : move_,
-            weight : 0,
-            learn  : 0,
-            win_white: w_white,
-            win_black: w_black,
-            total_games: 1,
+            key: u64_at(0),
+            move_: u16::from_be_bytes([bytes[8], bytes[9]]),
+            weight: 0,
+            learn: 0,
+            wins: f64::from_bits(u64_at(10)),
+            draws: f64::from_bits(u64_at(18)),
+            losses: f64::from_bits(u64_at(26)),
+            total_games: u32_at(34) as i32,
+            results: [u32_at(38), u32_at(42), u32_at(46)],
+            elo_sum: u64_at(50),
+            elo_games: u32_at(58),
+            last_year: u32_at(62),
+        }
+    }
+
+    pub fn stats (&self) -> SmoveStats {
+        SmoveStats {
+            key: self.key,
+            move_: self.move_,
+            wins: self.results[0],
+            draws: self.results[1],
+            losses: self.results[2],
+            elo: if self.elo_games > 0 { (self.elo_sum / self.elo_games as u64).min(u16::MAX as u64) as u16 } else { 0 },
+            year: self.last_year.min(u16::MAX as u32) as u16,
         }
     }
 }
@@ -92,15 +290,15 @@ impl Sbook {
     }
 
     pub fn insert_move (&mut self, hash_key: u64,
-            mov: u16, resul: i32) {
+            mov: u16, resul: i32, contribution: f64, elo: i32, year: u32) {
         
         let found = self.btree.get_mut(&hash_key);  // -> Option<&mut V>
         match found {
             Some(elems) => {
-                modify_node (elems, hash_key, mov, resul);
+                modify_node (elems, hash_key, mov, resul, contribution, elo, year);
             },
             None => { 
-                self.append_hash(hash_key, mov, resul);
+                self.append_hash(hash_key, mov, resul, contribution, elo, year);
             },
         };
         /*
@@ -112,9 +310,9 @@ impl Sbook {
     }
 
     pub fn append_hash (&mut self, hash_key: u64,
-            mov: u16, resul: i32) {
+            mov: u16, resul: i32, contribution: f64, elo: i32, year: u32) {
         
-        let entry = Sentry::create_entry(hash_key, mov, resul);
+        let entry = Sentry::create_entry(hash_key, mov, resul, contribution, elo, year);
         
         // create the new entry in BTree
         let mut vector: Vec<Sentry> = Vec::new();
@@ -124,11 +322,35 @@ impl Sbook {
     }
 
 
-    pub fn do_calculations (&mut self) {
+    // append_entry()
+    // an entry read from the file of -update
+
+    pub fn append_entry (&mut self, entry: Sentry) {
+        self.btree.entry(entry.key).or_default().push(entry);
+    }
+
+
+    pub fn do_calculations (&mut self, formula: WeightFormula) {
         for (_key, value) in self.btree.iter_mut() {
-            make_weight(value);
+            make_weight(value, formula);
         }
     }
+
+
+    // sort_entries()
+    // the moves of every position by descending weight, and by move if 
+    // they are the same, as the GUIs expect; the moves of weight 0 are 
+    // removed unless keep_zero
+
+    pub fn sort_entries (&mut self, keep_zero: bool) {
+        for (_key, value) in self.btree.iter_mut() {
+            if !keep_zero {
+                value.retain(|elem| elem.weight > 0);
+            }
+            value.sort_by(|a, b| b.weight.cmp(&a.weight).then(a.move_.cmp(&b.move_)));
+        }
+        self.btree.retain(|_key, value| !value.is_empty());
+    }
     
 }
 
@@ -136,40 +358,83 @@ impl Sbook {
 
 // External functions
 
-pub fn modify_node (elems: &mut Vec<Sentry>, hash_key: u64, mov: u16, resul: i32) {
+pub fn modify_node (elems: &mut Vec<Sentry>, hash_key: u64, mov: u16, resul: i32, contribution: f64, 
+        elo: i32, year: u32) {
 
     // find hash in vector
     let mut found: bool = false;
 
-    for i in 0..elems.len() {
-        if elems[i].key == hash_key && elems[i].move_ == mov {
-            if resul < 0 {
-                elems[i].win_black += 1;
-            }
-            else {
-                elems[i].win_white += 1;
-            }
-            elems[i].total_games += 1;
+    for elem in elems.iter_mut() {
+        if elem.key == hash_key && elem.move_ == mov {
+            elem.add_game(resul, contribution, elo, year);
             found = true;
             break;
         } 
     }
 
     if !found {
-        let new_elem = Sentry::create_entry(hash_key, mov, resul);
+        let new_elem = Sentry::create_entry(hash_key, mov, resul, contribution, elo, year);
         elems.push(new_elem);
     }
 
 }
 
 
-fn make_weight (elems: &mut Vec<Sentry>) {
-    for i in 0..elems.len() {
-        // 2*(win_white)+(draws);  draws = total_games - win_white - win_black)
-        let draws: i32 = elems[i].total_games -
-                            elems[i].win_white -
-                            elems[i].win_black;
-        let resul: i32 = 2 * elems[i].win_white + draws;
-        elems[i].weight = resul as u16;
+fn make_weight (elems: &mut [Sentry], formula: WeightFormula) {
+    let position_games: f64 = elems.iter().map(|elem| elem.wins + elem.draws + elem.losses).sum();
+    let weights: Vec<f64> = elems.iter().map(|elem| formula_weight(elem, formula, position_games)).collect();
+
+    // the weights of a position are scaled down together if one of them 
+    // does not fit in 16 bits
+    let max_weight = weights.iter().cloned().fold(0.0, f64::max);
+    let scale = if max_weight > u16::MAX as f64 { u16::MAX as f64 / max_weight } else { 1.0 };
+
+    for (elem, weight) in elems.iter_mut().zip(weights.iter()) {
+        elem.weight = (weight * scale).round().min(u16::MAX as f64) as u16;
+    }
+}
+
+
+// formula_weight()
+// the weight of a move before it is scaled, position_games are the games 
+// of all the moves of its position
+
+fn formula_weight (elem: &Sentry, formula: WeightFormula, position_games: f64) -> f64 {
+    let games: f64 = elem.wins + elem.draws + elem.losses;
+
+    match formula {
+        WeightFormula::Polyglot => 2.0 * elem.wins + elem.draws,
+        WeightFormula::Frequency => games,
+        WeightFormula::Score => {
+            if games > 0.0 {
+                // 0 to 10000, the same scale in every position
+                let score = 100.0 * (elem.wins + elem.draws / 2.0) / games;
+                score * 100.0 * games / position_games
+            }
+            else {
+                0.0
+            }
+        },
+        WeightFormula::Wins => elem.wins,
+        WeightFormula::ThreeOneZero => 3.0 * elem.wins + elem.draws,
+        WeightFormula::Wilson => 1000.0 * wilson_lower_bound(elem.wins + elem.draws / 2.0, games),
+        WeightFormula::Uniform => 1.0,
+    }
+}
+
+
+// wilson_lower_bound()
+// the lower bound of the 95% interval of a score of points in games
+
+fn wilson_lower_bound (points: f64, games: f64) -> f64 {
+    const Z: f64 = 1.96;
+
+    if games <= 0.0 {
+        return 0.0;
     }
+    let p = points / games;
+    let z2 = Z * Z;
+    let center = p + z2 / (2.0 * games);
+    let margin = Z * ((p * (1.0 - p) + z2 / (4.0 * games)) / games).sqrt();
+    ((center - margin) / (1.0 + z2 / games)).max(0.0)
 }
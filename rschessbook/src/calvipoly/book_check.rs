@@ -0,0 +1,205 @@
+use super::{book, board, moves, util, dump_book};
+
+use std::collections::HashMap;
+
+
+
+/* The problems of a book: the bytes of an incomplete entry at the end, 
+*  the entries out of order, the repeated (key, move) and the entries 
+*  that are not reached from the initial position or whose move is not 
+*  legal in the position reached; messages describes every entry */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheckReport {
+    pub entries: usize,
+    pub trailing_bytes: usize,
+    pub unsorted: usize,
+    pub duplicates: usize,
+    pub unreachable: usize,
+    pub illegal: usize,
+    pub messages: Vec<String>,
+}
+
+impl ScheckReport {
+    pub fn is_valid(&self) -> bool {
+        self.trailing_bytes == 0 && self.unsorted == 0 && self.duplicates == 0 && 
+            self.unreachable == 0 && self.illegal == 0
+    }
+}
+
+
+
+// check_book()
+// the problems of a book in the standard output, it exits with 
+// EXIT_FAILURE if there are any
+
+pub fn check_book(bin_file: &str) {
+
+    let report = match check_book_file(bin_file) {
+        Ok(report) => report,
+        Err(e) => {
+            let tmp = format!("check_book(): error {} reading book file {}\n", e, bin_file);
+            util::my_fatal(tmp.as_str());
+            return;
+        },
+    };
+
+    for message in report.messages.iter() {
+        println!("{}", message);
+    }
+    println!("total checked entries: {}", report.entries);
+    println!("trailing bytes: {}", report.trailing_bytes);
+    println!("entries out of order: {}", report.unsorted);
+    println!("duplicated entries: {}", report.duplicates);
+    println!("unreachable entries: {}", report.unreachable);
+    println!("illegal moves: {}", report.illegal);
+    if report.is_valid() {
+        println!("the book is valid");
+    }
+    else {
+        println!("the book has errors");
+        std::process::exit(util::EXIT_FAILURE);
+    }
+}
+
+
+// check_book_file()
+
+pub fn check_book_file(bin_file: &str) -> std::io::Result<ScheckReport> {
+    let size = std::fs::metadata(bin_file)?.len() as usize;
+    let entries = book::read_book_file(bin_file)?;
+    Ok(check_entries(&entries, size % 16))
+}
+
+
+// check_entries()
+// the entries of a book as they are in the file
+
+pub fn check_entries(entries: &[book::SfinalEntry], trailing_bytes: usize) -> ScheckReport {
+
+    let mut report = ScheckReport {
+        entries: entries.len(),
+        trailing_bytes,
+        unsorted: 0,
+        duplicates: 0,
+        unreachable: 0,
+        illegal: 0,
+        messages: Vec::new(),
+    };
+    if trailing_bytes != 0 {
+        report.messages.push(format!("the size of the file is not a multiple of 16: {} bytes after the last entry", trailing_bytes));
+    }
+
+    let legal = legal_entries(entries);
+    let mut seen: HashMap<(u64, u16), usize> = HashMap::new();
+
+    for (index, entry) in entries.iter().enumerate() {
+        let name = format!("entry {} ({:016x} {})", index, entry.key, moves::get_uci_format(entry.move_).trim());
+
+        if index > 0 && entry.key < entries[index - 1].key {
+            report.messages.push(format!("{}: key lower than the one of entry {}", name, index - 1));
+            report.unsorted += 1;
+        }
+        if let Some(first) = seen.get(&(entry.key, entry.move_)) {
+            report.messages.push(format!("{}: duplicate of entry {}", name, first));
+            report.duplicates += 1;
+        }
+        else {
+            seen.insert((entry.key, entry.move_), index);
+        }
+        match legal.get(&entry.key) {
+            Some(moves) if moves.contains(&entry.move_) => (),
+            Some(_) => {
+                report.messages.push(format!("{}: illegal move in the position", name));
+                report.illegal += 1;
+            },
+            None => {
+                report.messages.push(format!("{}: position not reached from the initial one", name));
+                report.unreachable += 1;
+            },
+        }
+    }
+    report
+}
+
+
+// repair_book()
+// writes the entries of a book without its problems in out_file
+
+pub fn repair_book(bin_file: &str, out_file: &str) {
+
+    let entries = match book::read_book_file(bin_file) {
+        Ok(entries) => entries,
+        Err(e) => {
+            let tmp = format!("repair_book(): error {} reading book file {}\n", e, bin_file);
+            util::my_fatal(tmp.as_str());
+            return;
+        },
+    };
+    let repaired = repair_entries(&entries);
+
+    if let Err(e) = book::write_book_file(out_file, &repaired) {
+        let tmp = format!("repair_book(): error {} creating book file {}\n", e, out_file);
+        util::my_fatal(tmp.as_str());
+    }
+    println!("read entries: {}", entries.len());
+    println!("written entries: {}", repaired.len());
+    println!("removed entries: {}", entries.len() - repaired.len());
+}
+
+
+// repair_entries()
+// drops the unreachable entries and the illegal moves, merges the 
+// repeated (key, move) adding their weights (with the learn of the 
+// first one) and sorts by key, descending weight and move
+
+pub fn repair_entries(entries: &[book::SfinalEntry]) -> Vec<book::SfinalEntry> {
+
+    let legal = legal_entries(entries);
+    let mut merged: Vec<book::SfinalEntry> = Vec::new();
+    let mut index: HashMap<(u64, u16), usize> = HashMap::new();
+
+    for entry in entries.iter() {
+        if !legal.get(&entry.key).is_some_and(|moves| moves.contains(&entry.move_)) {
+            continue;
+        }
+        match index.get(&(entry.key, entry.move_)) {
+            Some(first) => {
+                merged[*first].weight = merged[*first].weight.saturating_add(entry.weight);
+            },
+            None => {
+                index.insert((entry.key, entry.move_), merged.len());
+                merged.push(entry.clone());
+            },
+        }
+    }
+
+    merged.sort_by(|a, b| a.key.cmp(&b.key).then(b.weight.cmp(&a.weight)).then(a.move_.cmp(&b.move_)));
+    merged
+}
+
+
+// legal_entries()
+// the moves of the book that are legal in the positions reached from 
+// the initial position, by key
+
+fn legal_entries(entries: &[book::SfinalEntry]) -> HashMap<u64, Vec<u16>> {
+
+    let positions = dump_book::walk_book(entries);
+    let mut board: board::Sboard = board::Sboard::new();
+    let mut legal: HashMap<u64, Vec<u16>> = HashMap::new();
+
+    for (key, book_moves) in book::entries_by_key(entries).iter() {
+        let position = match positions.get(key) {
+            Some(position) => position,
+            None => continue,
+        };
+        let moves = legal.entry(*key).or_default();
+        for entry in book_moves.iter() {
+            board.set_fen(position.fen.as_str());
+            if !moves.contains(&entry.move_) && dump_book::find_move(&mut board, entry.move_).is_some() {
+                moves.push(entry.move_);
+            }
+        }
+    }
+    legal
+}
@@ -0,0 +1,190 @@
+use super::{book, board, san, util, color, dump_book};
+
+use std::collections::{HashSet, VecDeque};
+
+
+
+// the labels of the positions in the graph
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DotLabel {
+    Path,       // the moves from the first position, as "1. e4 e5 2. Nf3"
+    Fen,        // the pieces and the side to move of the fen
+}
+
+impl DotLabel {
+    pub fn from_name(name: &str) -> Option<DotLabel> {
+        match name {
+            "path" => Some(DotLabel::Path),
+            "fen" => Some(DotLabel::Fen),
+            _ => None,
+        }
+    }
+}
+
+
+/* The options of book2dot */
+pub struct SdotOptions {
+    pub fen: String,
+    pub max_ply: usize,
+    pub min_share: f64,     // the percentage of the weights of the position
+    pub label: DotLabel,
+}
+
+impl SdotOptions {
+    pub fn new() -> Self {
+        SdotOptions {
+            fen: String::from(util::START_FEN),
+            max_ply: 6,
+            min_share: 0.0,
+            label: DotLabel::Path,
+        }
+    }
+}
+
+
+
+// book2dot()
+// the tree of a book in a dot file, or in the standard output without file
+
+pub fn book2dot(bin_file: &str, options: &SdotOptions, dot_file: Option<&str>) {
+
+    let entries = match book::read_book_file(bin_file) {
+        Ok(entries) => entries,
+        Err(e) => {
+            let tmp = format!("book2dot(): error {} reading book file {}\n", e, bin_file);
+            util::my_fatal(tmp.as_str());
+            Vec::new()
+        },
+    };
+    let text = book_to_dot(&entries, options);
+
+    match dot_file {
+        Some(dot_file) => {
+            if let Err(e) = std::fs::write(dot_file, text) {
+                let tmp = format!("book2dot(): error {} creating file {}\n", e, dot_file);
+                util::my_fatal(tmp.as_str());
+            }
+        },
+        None => print!("{}", text),
+    }
+}
+
+
+// book_to_dot()
+// the graph of the book: the positions are the nodes, once even if they 
+// are reached by several lines, and the moves are the edges with their 
+// weight and percentage; a move of less than min_share is not drawn
+
+pub fn book_to_dot(entries: &[book::SfinalEntry], options: &SdotOptions) -> String {
+
+    let mut board: board::Sboard = board::Sboard::new();
+    if !board.set_fen(options.fen.as_str()) {
+        let tmp = format!("book_to_dot(): invalid FEN \"{}\"\n", options.fen);
+        util::my_fatal(tmp.as_str());
+    }
+    let first_move: usize = options.fen.split_whitespace().nth(5).and_then(|n| n.parse().ok()).unwrap_or(1).max(1);
+    let first_side = board.side;
+    let letters = san::piece_letters("en").unwrap();
+    let positions = book::entries_by_key(entries);
+
+    let mut lines: Vec<String> = vec![
+        String::from("digraph book {"),
+        String::from("    node [shape=box, fontname=\"Helvetica\"];"),
+        String::from("    edge [fontname=\"Helvetica\"];"),
+    ];
+
+    // the nodes in the order they are found, by the shortest line
+    let mut seen: HashSet<u64> = HashSet::new();
+    let mut queue: VecDeque<(u64, String, Vec<String>)> = VecDeque::new();
+
+    let root_fen = board.get_fen();
+    let label = node_label(options.label, &root_fen, first_move, first_side, &[]);
+    lines.push(format!("    {} [label=\"{}\"];", node_id(board.hash_key), label));
+    seen.insert(board.hash_key);
+    queue.push_back((board.hash_key, root_fen, Vec::new()));
+
+    while let Some((key, fen, path)) = queue.pop_front() {
+        if path.len() >= options.max_ply {
+            continue;
+        }
+        let entries = match positions.get(&key) {
+            Some(entries) => entries,
+            None => continue,
+        };
+        let total: u64 = entries.iter().map(|e| e.weight as u64).sum();
+
+        for entry in entries.iter() {
+            let share = if total > 0 { 100.0 * entry.weight as f64 / total as f64 } else { 0.0 };
+            if share < options.min_share {
+                continue;
+            }
+            board.set_fen(fen.as_str());
+            let mut m = match dump_book::find_move(&mut board, entry.move_) {
+                Some(m) => m,
+                None => continue,
+            };
+            let san = san::move_to_san(&m, &mut board, letters);
+            board.make_move(&mut m);
+            let next = board.hash_key;
+
+            // a transposition is an edge to a node that already exists
+            if seen.insert(next) {
+                let mut next_path = path.clone();
+                next_path.push(san.clone());
+                let next_fen = dump_book::book_fen(&mut board, first_ply(first_move, first_side) + next_path.len());
+                let label = node_label(options.label, &next_fen, first_move, first_side, &next_path);
+                lines.push(format!("    {} [label=\"{}\"];", node_id(next), label));
+                queue.push_back((next, next_fen, next_path));
+            }
+            lines.push(format!("    {} -> {} [label=\"{}\\n{} ({:.1}%)\", penwidth={:.1}];",
+                node_id(key), node_id(next), san, entry.weight, share, 1.0 + share / 25.0));
+        }
+    }
+
+    lines.push(String::from("}"));
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+
+// node_id()
+
+fn node_id(key: u64) -> String {
+    format!("n{:016x}", key)
+}
+
+
+// first_ply()
+// the half-moves from the initial position until the first position
+
+fn first_ply(first_move: usize, first_side: usize) -> usize {
+    (first_move - 1) * 2 + if first_side == color::BLACK { 1 } else { 0 }
+}
+
+
+// node_label()
+// the label of a position reached by the moves of path (in san)
+
+fn node_label(label: DotLabel, fen: &str, first_move: usize, first_side: usize, path: &[String]) -> String {
+    match label {
+        DotLabel::Fen => fen.split_whitespace().take(2).collect::<Vec<&str>>().join(" "),
+        DotLabel::Path => {
+            if path.is_empty() {
+                return String::from("start");
+            }
+            let mut tokens: Vec<String> = Vec::new();
+            for (index, san) in path.iter().enumerate() {
+                let ply = first_ply(first_move, first_side) + index;
+                if ply.is_multiple_of(2) {
+                    tokens.push(format!("{}.", ply / 2 + 1));
+                }
+                else if index == 0 {
+                    tokens.push(format!("{}...", ply / 2 + 1));
+                }
+                tokens.push(san.clone());
+            }
+            tokens.join(" ")
+        },
+    }
+}
@@ -0,0 +1,152 @@
+use super::{book, board, dump_book, moves, san};
+
+
+
+// the errors of the edition of a book
+
+#[derive(Debug, PartialEq)]
+pub enum EditError {
+    InvalidFen(String),
+    Move(san::SanError),        // the move is not legal in the position
+    MoveInBook,                 // add a move already in the position
+    MoveNotInBook,              // change a move that is not in the position
+}
+
+impl std::fmt::Display for EditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EditError::InvalidFen(fen) => write!(f, "invalid FEN \"{}\"", fen),
+            EditError::Move(e) => write!(f, "{}", e),
+            EditError::MoveInBook => write!(f, "the move is already in the book"),
+            EditError::MoveNotInBook => write!(f, "the move is not in the book"),
+        }
+    }
+}
+
+
+
+/* A book in memory to change by hand: the positions are given by their 
+*  fen and the moves in SAN (or uci), and it is saved sorted */
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeditableBook {
+    pub entries: Vec<book::SfinalEntry>,
+}
+
+impl SeditableBook {
+    pub fn new(entries: Vec<book::SfinalEntry>) -> Self {
+        SeditableBook {
+            entries,
+        }
+    }
+
+
+    // load()
+
+    pub fn load(bin_file: &str) -> std::io::Result<Self> {
+        Ok(SeditableBook::new(book::read_book_file(bin_file)?))
+    }
+
+
+    // save()
+    // the entries by key, descending weight and move
+
+    pub fn save(&mut self, bin_file: &str) -> std::io::Result<()> {
+        self.entries.sort_by(|a, b| a.key.cmp(&b.key).then(b.weight.cmp(&a.weight)).then(a.move_.cmp(&b.move_)));
+        book::write_book_file(bin_file, &self.entries)
+    }
+
+
+    // add_move()
+
+    pub fn add_move(&mut self, fen: &str, san: &str, weight: u16) -> Result<(), EditError> {
+        let (key, move_) = resolve(fen, san)?;
+        if self.position(key, move_).is_some() {
+            return Err(EditError::MoveInBook);
+        }
+        self.entries.push(book::SfinalEntry::new(key, move_, weight, 0));
+        Ok(())
+    }
+
+
+    // remove_move()
+
+    pub fn remove_move(&mut self, fen: &str, san: &str) -> Result<(), EditError> {
+        let (key, move_) = resolve(fen, san)?;
+        let before = self.entries.len();
+        self.entries.retain(|e| e.key != key || e.move_ != move_);
+        if self.entries.len() == before {
+            return Err(EditError::MoveNotInBook);
+        }
+        Ok(())
+    }
+
+
+    // set_weight()
+
+    pub fn set_weight(&mut self, fen: &str, san: &str, weight: u16) -> Result<(), EditError> {
+        let (key, move_) = resolve(fen, san)?;
+        match self.position(key, move_) {
+            Some(index) => {
+                self.entries[index].weight = weight;
+                Ok(())
+            },
+            None => Err(EditError::MoveNotInBook),
+        }
+    }
+
+
+    // forbid_move()
+    // removes the move of the position from the other positions of the book 
+    // where the same piece makes it: the ones reached from the initial position 
+    // (the piece of the others is not known); the number removed
+
+    pub fn forbid_move(&mut self, fen: &str, san: &str) -> Result<usize, EditError> {
+        let (mut board, m) = parse_move(fen, san)?;
+        let (key, move_, from) = (board.hash_key, m.encoded_move, m.from);
+        let (piece, color) = (board.piece[from], board.color[from]);
+
+        let positions = dump_book::walk_book(&self.entries);
+        let before = self.entries.len();
+        self.entries.retain(|e| {
+            let same_piece = e.key == key || positions.get(&e.key).is_some_and(|position| {
+                board.set_fen(position.fen.as_str()) && board.piece[from] == piece && board.color[from] == color
+            });
+            e.move_ != move_ || !same_piece
+        });
+        Ok(before - self.entries.len())
+    }
+
+
+    // position()
+    // the index of the entry of the move in the position
+
+    fn position(&self, key: u64, move_: u16) -> Option<usize> {
+        self.entries.iter().position(|e| e.key == key && e.move_ == move_)
+    }
+}
+
+
+
+// resolve()
+// the key of the position and the polyglot encoding of the move
+
+fn resolve(fen: &str, san: &str) -> Result<(u64, u16), EditError> {
+    let (board, m) = parse_move(fen, san)?;
+    Ok((board.hash_key, m.encoded_move))
+}
+
+
+// parse_move()
+// the board of the position and the move
+
+fn parse_move(fen: &str, san: &str) -> Result<(board::Sboard, moves::Smove), EditError> {
+    let mut board: board::Sboard = board::Sboard::new();
+    if !board.set_fen(fen) {
+        return Err(EditError::InvalidFen(fen.to_string()));
+    }
+    let mut parser = san::SsanParser::new();
+    match parser.parse(san, &mut board) {
+        Ok(m) => Ok((board, m)),
+        Err(e) => Err(EditError::Move(e)),
+    }
+}
@@ -0,0 +1,213 @@
+use super::{book, board, moves, san, util, color, dump_book};
+
+use std::collections::{HashMap, HashSet};
+
+
+
+/* The state of book2pgn while the tree is written */
+struct SpgnTree {
+    positions: HashMap<u64, Vec<book::SfinalEntry>>,
+    owners: HashMap<u64, (u64, u16)>,   // the position and move that write every position
+    max_ply: usize,
+    first_move: usize,              // the number of the first move of the fen
+    first_side: usize,
+    letters: &'static san::SpieceLetters,
+    tokens: Vec<String>,
+}
+
+
+
+// book2pgn()
+// the tree of a book in a pgn file, or in the standard output without file
+
+pub fn book2pgn(bin_file: &str, fen: &str, max_ply: usize, pgn_file: Option<&str>) {
+
+    let entries = match book::read_book_file(bin_file) {
+        Ok(entries) => entries,
+        Err(e) => {
+            let tmp = format!("book2pgn(): error {} reading book file {}\n", e, bin_file);
+            util::my_fatal(tmp.as_str());
+            Vec::new()
+        },
+    };
+    let event = format!("rschessbook {}", bin_file);
+    let text = book_to_pgn(&entries, fen, max_ply, event.as_str());
+
+    match pgn_file {
+        Some(pgn_file) => {
+            if let Err(e) = std::fs::write(pgn_file, text) {
+                let tmp = format!("book2pgn(): error {} creating file {}\n", e, pgn_file);
+                util::my_fatal(tmp.as_str());
+            }
+        },
+        None => print!("{}", text),
+    }
+}
+
+
+// book_to_pgn()
+// the book from a position as one game, the moves of more weight first 
+// and the others as variations, with their weight and percentage
+
+pub fn book_to_pgn(entries: &[book::SfinalEntry], fen: &str, max_ply: usize, event: &str) -> String {
+
+    let mut board: board::Sboard = board::Sboard::new();
+    if !board.set_fen(fen) {
+        let tmp = format!("book_to_pgn(): invalid FEN \"{}\"\n", fen);
+        util::my_fatal(tmp.as_str());
+    }
+
+    let mut tree = SpgnTree {
+        positions: book::entries_by_key(entries),
+        owners: HashMap::new(),
+        max_ply,
+        first_move: fen.split_whitespace().nth(5).and_then(|n| n.parse().ok()).unwrap_or(1).max(1),
+        first_side: board.side,
+        letters: san::piece_letters("en").unwrap(),
+        tokens: Vec::new(),
+    };
+
+    let mut visited: HashSet<u64> = HashSet::new();
+    visited.insert(board.hash_key);
+    assign_owners(&mut tree, &mut board, 0, &mut visited);
+    write_node(&mut tree, &mut board, 0);
+
+    let mut lines: Vec<String> = vec![
+        format!("[Event \"{}\"]", event),
+        String::from("[Site \"?\"]"),
+        String::from("[Date \"????.??.??\"]"),
+        String::from("[Round \"?\"]"),
+        String::from("[White \"?\"]"),
+        String::from("[Black \"?\"]"),
+        String::from("[Result \"*\"]"),
+    ];
+    if fen != util::START_FEN {
+        lines.push(String::from("[SetUp \"1\"]"));
+        lines.push(format!("[FEN \"{}\"]", fen));
+    }
+    lines.push(String::new());
+
+    // the movetext in lines of 79 characters at most
+    tree.tokens.push(String::from("*"));
+    let mut line = String::new();
+    for token in tree.tokens.iter() {
+        if !line.is_empty() && line.len() + 1 + token.len() > 79 {
+            lines.push(line);
+            line = String::new();
+        }
+        if !line.is_empty() && !line.ends_with('(') && token != ")" {
+            line.push(' ');
+        }
+        line.push_str(token);
+    }
+    lines.push(line);
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+
+// assign_owners()
+// every position is written after the first move that reaches it, 
+// following the main lines before the variations; at the others it is
+// a transposition
+
+fn assign_owners(tree: &mut SpgnTree, board: &mut board::Sboard, ply: usize, visited: &mut HashSet<u64>) {
+
+    if ply >= tree.max_ply {
+        return;
+    }
+    let key = board.hash_key;
+    for (_entry, mut m) in book_moves(tree, board).into_iter() {
+        board.make_move(&mut m);
+        let next = board.hash_key;
+        if tree.positions.contains_key(&next) && visited.insert(next) {
+            tree.owners.insert(next, (key, m.encoded_move));
+            assign_owners(tree, board, ply + 1, visited);
+        }
+        board.undo_move(&mut m);
+    }
+}
+
+
+// book_moves()
+// the entries of the position with their legal moves, an illegal entry 
+// is not written
+
+fn book_moves(tree: &SpgnTree, board: &mut board::Sboard) -> Vec<(book::SfinalEntry, moves::Smove)> {
+    let mut found: Vec<(book::SfinalEntry, moves::Smove)> = Vec::new();
+    if let Some(entries) = tree.positions.get(&board.hash_key) {
+        for entry in entries.iter() {
+            if let Some(m) = dump_book::find_move(board, entry.move_) {
+                found.push((entry.clone(), m));
+            }
+        }
+    }
+    found
+}
+
+
+// write_node()
+// the moves of the book from the position of the board: the first one, 
+// the others as variations and then the line of the first one
+
+fn write_node(tree: &mut SpgnTree, board: &mut board::Sboard, ply: usize) {
+
+    if ply >= tree.max_ply {
+        return;
+    }
+    let moves = book_moves(tree, board);
+    if moves.is_empty() {
+        return;
+    }
+    let key = board.hash_key;
+    let total: u64 = moves.iter().map(|(e, _)| e.weight as u64).sum();
+
+    for (index, (entry, m)) in moves.iter().enumerate() {
+        if index > 0 {
+            tree.tokens.push(String::from("("));
+        }
+        // every move has a comment, the moves of black also have their number
+        tree.tokens.push(move_number(tree, ply, board.side));
+        tree.tokens.push(san::move_to_san(m, board, tree.letters));
+        let percentage = if total > 0 { 100.0 * entry.weight as f64 / total as f64 } else { 0.0 };
+        tree.tokens.push(format!("{{{} {:.1}%}}", entry.weight, percentage));
+
+        let mut m = m.clone();
+        board.make_move(&mut m);
+        let next = board.hash_key;
+        let owner = tree.owners.get(&next) == Some(&(key, m.encoded_move));
+        if tree.positions.contains_key(&next) && !owner {
+            tree.tokens.push(String::from("{transposition}"));
+        }
+        // the main line goes on after the variations
+        if index > 0 {
+            if owner {
+                write_node(tree, board, ply + 1);
+            }
+            tree.tokens.push(String::from(")"));
+        }
+        board.undo_move(&mut m);
+    }
+
+    let mut m = moves[0].1.clone();
+    board.make_move(&mut m);
+    if tree.owners.get(&board.hash_key) == Some(&(key, m.encoded_move)) {
+        write_node(tree, board, ply + 1);
+    }
+    board.undo_move(&mut m);
+}
+
+
+// move_number()
+// "5." for white, "5..." for black
+
+fn move_number(tree: &SpgnTree, ply: usize, side: usize) -> String {
+    let plies = ply + if tree.first_side == color::BLACK { 1 } else { 0 };
+    let number = tree.first_move + plies / 2;
+    if side == color::WHITE {
+        format!("{}.", number)
+    }
+    else {
+        format!("{}...", number)
+    }
+}
@@ -0,0 +1,124 @@
+use super::{book, board, util, dump_book};
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::hash_map::Entry;
+
+
+
+/* The options of prune-book */
+pub struct SpruneOptions {
+    pub roots: Vec<String>,     // the fens of the first positions
+    pub max_ply: usize,         // the half-moves followed from a root
+    pub min_share: f64,         // the percentage of the weights of the position
+}
+
+impl SpruneOptions {
+    pub fn new() -> Self {
+        SpruneOptions {
+            roots: vec![String::from(util::START_FEN)],
+            max_ply: usize::MAX,
+            min_share: 0.0,
+        }
+    }
+
+
+    // add_roots()
+    // the fens of a file, one by line (empty lines are skipped); 
+    // false if a fen is invalid
+
+    pub fn add_roots(&mut self, text: &str) -> bool {
+        let mut board: board::Sboard = board::Sboard::new();
+        for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            if !board.set_fen(line) {
+                return false;
+            }
+            self.roots.push(line.to_string());
+        }
+        true
+    }
+}
+
+
+
+// prune_book()
+// writes in out_file the entries of the book reached from the roots
+
+pub fn prune_book(bin_file: &str, out_file: &str, options: &SpruneOptions) {
+
+    let entries = match book::read_book_file(bin_file) {
+        Ok(entries) => entries,
+        Err(e) => {
+            let tmp = format!("prune_book(): error {} reading book file {}\n", e, bin_file);
+            util::my_fatal(tmp.as_str());
+            return;
+        },
+    };
+    let pruned = prune_entries(&entries, options);
+
+    if let Err(e) = book::write_book_file(out_file, &pruned) {
+        let tmp = format!("prune_book(): error {} creating book file {}\n", e, out_file);
+        util::my_fatal(tmp.as_str());
+    }
+    println!("read entries: {}", entries.len());
+    println!("written entries: {}", pruned.len());
+    println!("removed entries: {}", entries.len() - pruned.len());
+}
+
+
+// prune_entries()
+// the entries of the positions reached from the roots breadth first 
+// by the moves of the book, in the order of the book; a position 
+// max_ply half-moves from its nearest root is not kept, nor a move 
+// of less than min_share or illegal
+
+pub fn prune_entries(entries: &[book::SfinalEntry], options: &SpruneOptions) -> Vec<book::SfinalEntry> {
+
+    let positions = book::entries_by_key(entries);
+    let mut board: board::Sboard = board::Sboard::new();
+    let mut kept: HashSet<(u64, u16)> = HashSet::new();
+    let mut reached: HashMap<u64, usize> = HashMap::new();
+    let mut queue: VecDeque<(u64, String)> = VecDeque::new();
+
+    for fen in options.roots.iter() {
+        if !board.set_fen(fen.as_str()) {
+            let tmp = format!("prune_entries(): invalid FEN \"{}\"\n", fen);
+            util::my_fatal(tmp.as_str());
+        }
+        if let Entry::Vacant(root) = reached.entry(board.hash_key) {
+            root.insert(0);
+            queue.push_back((board.hash_key, fen.clone()));
+        }
+    }
+
+    while let Some((key, fen)) = queue.pop_front() {
+        let ply = reached[&key];
+        if ply >= options.max_ply {
+            continue;
+        }
+        let book_moves = match positions.get(&key) {
+            Some(book_moves) => book_moves,
+            None => continue,
+        };
+        let total: u64 = book_moves.iter().map(|e| e.weight as u64).sum();
+
+        for entry in book_moves.iter() {
+            let share = if total > 0 { 100.0 * entry.weight as f64 / total as f64 } else { 0.0 };
+            if share < options.min_share {
+                continue;
+            }
+            board.set_fen(fen.as_str());
+            let mut m = match dump_book::find_move(&mut board, entry.move_) {
+                Some(m) => m,
+                None => continue,
+            };
+            kept.insert((entry.key, entry.move_));
+            board.make_move(&mut m);
+            if let Entry::Vacant(next) = reached.entry(board.hash_key) {
+                next.insert(ply + 1);
+                queue.push_back((board.hash_key, board.get_fen()));
+            }
+        }
+    }
+
+    entries.iter().filter(|e| kept.contains(&(e.key, e.move_))).cloned().collect()
+}
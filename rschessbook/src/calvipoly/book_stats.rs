@@ -0,0 +1,155 @@
+use super::{book, board, util, dump_book};
+
+use std::collections::{BTreeMap, HashSet};
+
+
+
+// the limits of the groups of the weight distribution
+
+const WEIGHT_GROUPS: [(u16, u16); 6] = [
+    (0, 0),
+    (1, 1),
+    (2, 9),
+    (10, 99),
+    (100, 999),
+    (1000, u16::MAX),
+];
+
+
+
+/* The shape of a book: its size, the moves of its positions, its weights,
+*  the entries reached from the initial position (by ply) and the ones 
+*  that are not reached, the illegal moves and the repeated (key, move) */
+#[derive(Debug, Clone, PartialEq)]
+pub struct SbookStats {
+    pub entries: usize,
+    pub positions: usize,
+    pub max_moves: usize,
+    pub weight_min: u16,
+    pub weight_max: u16,
+    pub weight_sum: u64,
+    pub weight_median: u16,
+    pub weight_groups: Vec<usize>,              // the entries of every WEIGHT_GROUPS
+    pub plies: BTreeMap<usize, (usize, usize)>, // ply -> (positions, entries)
+    pub unreachable_positions: usize,
+    pub unreachable_entries: usize,
+    pub illegal_moves: usize,                   // in the positions reached
+    pub duplicates: usize,                      // the repetitions of a (key, move)
+}
+
+
+
+// book_stats()
+// the report of a book in the standard output
+
+pub fn book_stats(bin_file: &str) {
+
+    let entries = match book::read_book_file(bin_file) {
+        Ok(entries) => entries,
+        Err(e) => {
+            let tmp = format!("book_stats(): error {} reading book file {}\n", e, bin_file);
+            util::my_fatal(tmp.as_str());
+            Vec::new()
+        },
+    };
+    print!("{}", format_stats(&compute_stats(&entries)));
+}
+
+
+// compute_stats()
+
+pub fn compute_stats(entries: &[book::SfinalEntry]) -> SbookStats {
+
+    let reached = dump_book::walk_book(entries);
+    let positions = book::entries_by_key(entries);
+    let mut board: board::Sboard = board::Sboard::new();
+
+    let mut weights: Vec<u16> = entries.iter().map(|e| e.weight).collect();
+    weights.sort_unstable();
+
+    let mut stats = SbookStats {
+        entries: entries.len(),
+        positions: positions.len(),
+        max_moves: positions.values().map(|p| p.len()).max().unwrap_or(0),
+        weight_min: weights.first().copied().unwrap_or(0),
+        weight_max: weights.last().copied().unwrap_or(0),
+        weight_sum: weights.iter().map(|w| *w as u64).sum(),
+        weight_median: if weights.is_empty() { 0 } else { weights[(weights.len() - 1) / 2] },
+        weight_groups: WEIGHT_GROUPS.iter()
+            .map(|(low, high)| weights.iter().filter(|w| **w >= *low && **w <= *high).count())
+            .collect(),
+        plies: BTreeMap::new(),
+        unreachable_positions: 0,
+        unreachable_entries: 0,
+        illegal_moves: 0,
+        duplicates: 0,
+    };
+
+    for (key, moves) in positions.iter() {
+        match reached.get(key) {
+            Some(position) => {
+                let ply = stats.plies.entry(position.ply).or_insert((0, 0));
+                ply.0 += 1;
+                ply.1 += moves.len();
+                for entry in moves.iter() {
+                    board.set_fen(position.fen.as_str());
+                    if dump_book::find_move(&mut board, entry.move_).is_none() {
+                        stats.illegal_moves += 1;
+                    }
+                }
+            },
+            None => {
+                stats.unreachable_positions += 1;
+                stats.unreachable_entries += moves.len();
+            },
+        }
+    }
+
+    let mut seen: HashSet<(u64, u16)> = HashSet::new();
+    stats.duplicates = entries.iter().filter(|e| !seen.insert((e.key, e.move_))).count();
+
+    stats
+}
+
+
+// format_stats()
+// one value by line, to compare the reports of two books with diff
+
+pub fn format_stats(stats: &SbookStats) -> String {
+
+    let mean = |total: f64, number: usize| -> f64 {
+        if number == 0 { 0.0 } else { total / number as f64 }
+    };
+    let mut lines: Vec<String> = vec![
+        format!("entries               {}", stats.entries),
+        format!("positions             {}", stats.positions),
+        format!("moves per position    max {} mean {:.2}", 
+            stats.max_moves, mean(stats.entries as f64, stats.positions)),
+        format!("weights               min {} max {} mean {:.2} median {}", 
+            stats.weight_min, stats.weight_max, mean(stats.weight_sum as f64, stats.entries), stats.weight_median),
+    ];
+
+    for ((low, high), number) in WEIGHT_GROUPS.iter().zip(stats.weight_groups.iter()) {
+        let group = if low == high {
+            format!("{}", low)
+        }
+        else if *high == u16::MAX {
+            format!("{}+", low)
+        }
+        else {
+            format!("{}-{}", low, high)
+        };
+        lines.push(format!("weight {:<14} {}", group, number));
+    }
+
+    for (ply, (positions, entries)) in stats.plies.iter() {
+        lines.push(format!("ply {:<17} {} entries in {} positions", ply, entries, positions));
+    }
+
+    lines.push(format!("unreachable           {} entries in {} positions", 
+        stats.unreachable_entries, stats.unreachable_positions));
+    lines.push(format!("illegal moves         {}", stats.illegal_moves));
+    lines.push(format!("duplicates            {}", stats.duplicates));
+    lines.push(String::new());
+    lines.join("\n")
+}
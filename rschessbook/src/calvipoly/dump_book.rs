@@ -0,0 +1,187 @@
+use super::{book, board, moves, san, util};
+
+use std::collections::{HashMap, VecDeque};
+
+
+
+// the formats of dump-book
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DumpFormat {
+    Text,
+    Csv,
+    Json,
+}
+
+impl DumpFormat {
+    pub fn from_name(name: &str) -> Option<DumpFormat> {
+        match name {
+            "text" => Some(DumpFormat::Text),
+            "csv" => Some(DumpFormat::Csv),
+            "json" => Some(DumpFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+
+
+/* A position of the book reached from the initial position by its moves,
+*  ply is the length of the shortest line to it */
+#[derive(Clone, Debug)]
+pub struct SbookPosition {
+    pub fen: String,
+    pub ply: usize,
+}
+
+
+
+// dump_book()
+// the entries of a book, in the order of the file
+
+pub fn dump_book(bin_file: &str, format: DumpFormat) {
+
+    let entries = match book::read_book_file(bin_file) {
+        Ok(entries) => entries,
+        Err(e) => {
+            let tmp = format!("dump_book(): error {} reading book file {}\n", e, bin_file);
+            util::my_fatal(tmp.as_str());
+            Vec::new()
+        },
+    };
+    print!("{}", format_book(&entries, format));
+}
+
+
+// format_book()
+// every entry as key (hex), uci move, weight and learn, and the san 
+// and fen if the position is reached from the initial one
+
+pub fn format_book(entries: &[book::SfinalEntry], format: DumpFormat) -> String {
+
+    let positions = walk_book(entries);
+    let mut board: board::Sboard = board::Sboard::new();
+    let letters = san::piece_letters("en").unwrap();
+    let mut lines: Vec<String> = Vec::new();
+
+    match format {
+        DumpFormat::Csv => lines.push(String::from("key,move,weight,learn,san,fen")),
+        DumpFormat::Json => lines.push(String::from("[")),
+        DumpFormat::Text => (),
+    }
+
+    for (index, entry) in entries.iter().enumerate() {
+        let mut uci = moves::get_uci_format(entry.move_).trim().to_string();
+        let mut san_fen: Option<(String, &str)> = None;
+
+        if let Some(position) = positions.get(&entry.key) {
+            board.set_fen(position.fen.as_str());
+            if let Some(m) = find_move(&mut board, entry.move_) {
+                uci = m.to_uci();
+                san_fen = Some((san::move_to_san(&m, &mut board, letters), position.fen.as_str()));
+            }
+        }
+
+        let line = match format {
+            DumpFormat::Text => {
+                let (san, fen) = san_fen.unwrap_or_default();
+                format!("{:016x} {:<5} {:>5} {:>10} {:<7} {}", 
+                    entry.key, uci, entry.weight, entry.learn, san, fen).trim_end().to_string()
+            },
+            DumpFormat::Csv => {
+                let (san, fen) = san_fen.unwrap_or_default();
+                format!("{:016x},{},{},{},{},{}", entry.key, uci, entry.weight, entry.learn, san, fen)
+            },
+            DumpFormat::Json => {
+                let (san, fen) = match san_fen {
+                    Some((san, fen)) => (format!("\"{}\"", san), format!("\"{}\"", fen)),
+                    None => (String::from("null"), String::from("null")),
+                };
+                let comma = if index + 1 < entries.len() { "," } else { "" };
+                format!("  {{\"key\": \"{:016x}\", \"move\": \"{}\", \"weight\": {}, \"learn\": {}, \"san\": {}, \"fen\": {}}}{}",
+                    entry.key, uci, entry.weight, entry.learn, san, fen, comma)
+            },
+        };
+        lines.push(line);
+    }
+
+    if format == DumpFormat::Json {
+        lines.push(String::from("]"));
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+
+// walk_book()
+// the positions of the book reached from the initial position by the 
+// moves of the book, breadth first (the first line found is the shortest)
+
+pub fn walk_book(entries: &[book::SfinalEntry]) -> HashMap<u64, SbookPosition> {
+
+    let mut book_moves: HashMap<u64, Vec<u16>> = HashMap::new();
+    for entry in entries.iter() {
+        book_moves.entry(entry.key).or_default().push(entry.move_);
+    }
+
+    let mut positions: HashMap<u64, SbookPosition> = HashMap::new();
+    let mut queue: VecDeque<u64> = VecDeque::new();
+    let mut board: board::Sboard = board::Sboard::new();
+
+    board.set_fen(util::START_FEN);
+    if book_moves.contains_key(&board.hash_key) {
+        positions.insert(board.hash_key, SbookPosition { fen: book_fen(&mut board, 0), ply: 0 });
+        queue.push_back(board.hash_key);
+    }
+
+    while let Some(key) = queue.pop_front() {
+        let position = positions[&key].clone();
+        for move_ in book_moves[&key].iter() {
+            board.set_fen(position.fen.as_str());
+            if let Some(mut m) = find_move(&mut board, *move_) {
+                board.make_move(&mut m);
+                let next = board.hash_key;
+                if book_moves.contains_key(&next) && !positions.contains_key(&next) {
+                    positions.insert(next, SbookPosition { fen: book_fen(&mut board, position.ply + 1), ply: position.ply + 1 });
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    positions
+}
+
+
+// book_fen()
+// the fen of the board ply half-moves after the initial position 
+// (get_fen() counts the half-moves, not the moves)
+
+pub fn book_fen(board: &mut board::Sboard, ply: usize) -> String {
+    let fen = board.get_fen();
+    match fen.rfind(' ') {
+        Some(space) => format!("{} {}", &fen[..space], ply / 2 + 1),
+        None => fen,
+    }
+}
+
+
+// find_move()
+// the legal move of the position with the polyglot encoding of a book entry
+
+pub fn find_move(board: &mut board::Sboard, move_: u16) -> Option<moves::Smove> {
+
+    let mut moves: Vec<moves::Smove> = Vec::new();
+    board.gen_moves(&mut moves);
+
+    for m in moves.iter_mut() {
+        if m.encoded_move != move_ {
+            continue;
+        }
+        let legal = board.make_move(m).is_some();
+        board.undo_move(m);
+        if legal {
+            return Some(m.clone());
+        }
+    }
+    None
+}
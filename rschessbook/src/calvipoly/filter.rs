@@ -0,0 +1,293 @@
+use super::{pgn, color};
+
+
+
+// the categories of the TimeControl tag, by the estimated duration
+// of the game in seconds: base + 40 * increment
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TimeControl {
+    UltraBullet,
+    Bullet,
+    Blitz,
+    Rapid,
+    Classical,
+    Correspondence,
+}
+
+const TIME_CONTROL_NAMES: [(&str, TimeControl); 6] = [
+    ("ultrabullet", TimeControl::UltraBullet),
+    ("bullet", TimeControl::Bullet),
+    ("blitz", TimeControl::Blitz),
+    ("rapid", TimeControl::Rapid),
+    ("classical", TimeControl::Classical),
+    ("correspondence", TimeControl::Correspondence),
+];
+
+
+
+/* The games that go to the book, by their tags.
+*  A filter on a tag rejects the games without that tag */
+#[derive(Clone)]
+pub struct SgameFilter {
+    pub min_elo: Option<i32>,           // at least one of the players
+    pub min_elo_both: Option<i32>,      // both players
+    pub date_from: Option<u32>,         // as yyyymmdd
+    pub date_to: Option<u32>,
+    pub event: Option<String>,          // substrings, in lowercase
+    pub site: Option<String>,
+    pub time_controls: Vec<TimeControl>,
+    pub only_standard: bool,            // skip chess960 and other variants
+    pub only_finished: bool,            // skip the games with result "*"
+    pub player: Option<String>,         // the games of a player
+    pub player_color: Option<usize>,    // only with white or black
+}
+
+impl SgameFilter {
+    pub fn new() -> Self {
+        SgameFilter {
+            min_elo: None,
+            min_elo_both: None,
+            date_from: None,
+            date_to: None,
+            event: None,
+            site: None,
+            time_controls: Vec::new(),
+            only_standard: true,
+            only_finished: false,
+            player: None,
+            player_color: None,
+        }
+    }
+
+
+    // player_side()
+    // the color of the player in the game, None without player or if 
+    // the player has not played it (with the color asked)
+
+    pub fn player_side(&self, pgn: &pgn::Spgn) -> Option<usize> {
+
+        let player = self.player.as_ref()?;
+
+        for (tag, side) in [("White", color::WHITE), ("Black", color::BLACK)].iter() {
+            if self.player_color.is_some_and(|c| c != *side) {
+                continue;
+            }
+            if same_player(player, pgn.get_tag(tag).unwrap_or("")) {
+                return Some(*side);
+            }
+        }
+        None
+    }
+
+
+    // set_date_from()
+    // yyyy[.mm[.dd]], the missing parts are the first day; false if malformed
+
+    pub fn set_date_from(&mut self, date: &str) -> bool {
+        self.date_from = parse_date(date, 0);
+        self.date_from.is_some()
+    }
+
+
+    // set_date_to()
+    // yyyy[.mm[.dd]], the missing parts are the last day; false if malformed
+
+    pub fn set_date_to(&mut self, date: &str) -> bool {
+        self.date_to = parse_date(date, 99);
+        self.date_to.is_some()
+    }
+
+
+    // set_time_controls()
+    // a list as "blitz,rapid"; false if a category is unknown
+
+    pub fn set_time_controls(&mut self, names: &str) -> bool {
+        self.time_controls.clear();
+        for name in names.split(',') {
+            let name = name.trim().to_ascii_lowercase();
+            match TIME_CONTROL_NAMES.iter().find(|(n, _)| *n == name) {
+                Some((_, category)) => self.time_controls.push(*category),
+                None => return false,
+            }
+        }
+        true
+    }
+
+
+    // accepts()
+    // the game that has been read by pgn_next_game() passes the filter
+
+    pub fn accepts(&self, pgn: &pgn::Spgn) -> bool {
+
+        if self.only_finished && pgn.get_tag("Result").unwrap_or("*").trim() == "*" {
+            return false;
+        }
+
+        if self.player.is_some() && self.player_side(pgn).is_none() {
+            return false;
+        }
+
+        if self.only_standard {
+            if let Some(variant) = pgn.get_tag("Variant") {
+                let variant = variant.trim().to_ascii_lowercase();
+                if !variant.is_empty() && variant != "standard" && variant != "chess" {
+                    return false;
+                }
+            }
+        }
+
+        if self.min_elo.is_some() || self.min_elo_both.is_some() {
+            let white = elo(pgn.get_tag("WhiteElo"));
+            let black = elo(pgn.get_tag("BlackElo"));
+            if let Some(min) = self.min_elo {
+                if white.max(black).unwrap_or(0) < min {
+                    return false;
+                }
+            }
+            if let Some(min) = self.min_elo_both {
+                if white.unwrap_or(0) < min || black.unwrap_or(0) < min {
+                    return false;
+                }
+            }
+        }
+
+        if self.date_from.is_some() || self.date_to.is_some() {
+            let date = match pgn.get_tag("Date").and_then(|d| parse_date(d, 0)) {
+                Some(date) => date,
+                None => return false,
+            };
+            if self.date_from.is_some_and(|from| date < from) ||
+                    self.date_to.is_some_and(|to| date > to) {
+                return false;
+            }
+        }
+
+        if !contains(pgn.get_tag("Event"), &self.event) || !contains(pgn.get_tag("Site"), &self.site) {
+            return false;
+        }
+
+        if !self.time_controls.is_empty() {
+            match pgn.get_tag("TimeControl").and_then(time_control) {
+                Some(category) => {
+                    if !self.time_controls.contains(&category) {
+                        return false;
+                    }
+                },
+                None => return false,
+            }
+        }
+
+        true
+    }
+}
+
+
+
+// elo()
+// None if the tag is missing, "?" or "-"
+
+fn elo(value: Option<&str>) -> Option<i32> {
+    value?.trim().parse::<i32>().ok().filter(|e| *e > 0)
+}
+
+
+// contains()
+// the tag has the text, without case; always true without text
+
+fn contains(value: Option<&str>, text: &Option<String>) -> bool {
+    match text {
+        Some(text) => value.is_some_and(|v| v.to_lowercase().contains(text.as_str())),
+        None => true,
+    }
+}
+
+
+// same_player()
+// the names are the same player: "Carlsen, Magnus", "Magnus Carlsen", 
+// "Carlsen,M." or only "Carlsen"; every word of the name must be in the 
+// tag, and an initial is the same as the word that begins with it
+
+pub fn same_player(name: &str, tag: &str) -> bool {
+    let words = |text: &str| -> Vec<String> {
+        text.split(|c: char| c == ',' || c == '.' || c.is_whitespace())
+            .filter(|w| !w.is_empty())
+            .map(|w| w.to_lowercase())
+            .collect()
+    };
+    let name_words = words(name);
+    let tag_words = words(tag);
+
+    let same_word = |a: &String, b: &String| -> bool {
+        a == b || 
+        (a.chars().count() == 1 && b.starts_with(a.as_str())) || 
+        (b.chars().count() == 1 && a.starts_with(b.as_str()))
+    };
+
+    !name_words.is_empty() && 
+        name_words.iter().all(|n| tag_words.iter().any(|t| same_word(n, t)))
+}
+
+
+// parse_date()
+// the date yyyy.mm.dd as a number yyyymmdd, the unknown or missing
+// month and day ("??") take the value missing; None if there is no year
+
+pub fn parse_date(date: &str, missing: u32) -> Option<u32> {
+    let mut parts = date.trim().split(['.', '/', '-']);
+
+    let year: u32 = parts.next()?.parse().ok()?;
+    let mut number = year;
+    for _ in 0..2 {
+        let part = parts.next().and_then(|p| p.parse::<u32>().ok()).unwrap_or(missing);
+        number = number * 100 + part;
+    }
+    Some(number)
+}
+
+
+// time_control()
+// the category of a TimeControl tag: "180+2", "300", "40/7200:3600", "1/86400"
+
+pub fn time_control(value: &str) -> Option<TimeControl> {
+    let value = value.trim();
+
+    // the first period of the game
+    let period = value.split(':').next()?;
+    let (moves, period) = match period.find('/') {
+        Some(slash) => (Some(&period[..slash]), &period[slash+1..]),
+        None => (None, period),
+    };
+    let mut parts = period.split('+');
+    let base: u32 = parts.next()?.parse().ok()?;
+    let increment: u32 = match parts.next() {
+        Some(inc) => inc.parse().ok()?,
+        None => 0,
+    };
+
+    // days per move
+    if moves == Some("1") && base >= 86400 {
+        return Some(TimeControl::Correspondence);
+    }
+
+    let duration = base + 40 * increment;
+    let category = if duration < 30 {
+        TimeControl::UltraBullet
+    }
+    else if duration < 180 {
+        TimeControl::Bullet
+    }
+    else if duration < 480 {
+        TimeControl::Blitz
+    }
+    else if duration < 1500 {
+        TimeControl::Rapid
+    }
+    else if duration < 86400 {
+        TimeControl::Classical
+    }
+    else {
+        TimeControl::Correspondence
+    };
+    Some(category)
+}
@@ -1,6 +1,9 @@
-use super::{book, pgn, board, util, san};
+use super::{book, pgn, board, util, san, moves, filter, color};
 
 use std::time::{Instant};
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 //use std::io;
 use std::io::prelude::*;
 use std::fs::File;
@@ -13,25 +16,293 @@ static mut MAX_PLY: i32 = 1024;
 
 
 
+/* How much a game counts in the book (1 without weights): 
+*  the contribution doubles every elo_points of the side that moves 
+*  above elo_threshold, and halves every half_life years that the game 
+*  is older than date_reference. The games without a date count as 
+*  if they were half_life years old */
+#[derive(Clone)]
+pub struct SgameWeight {
+    pub elo_threshold: Option<i32>,
+    pub elo_points: f64,
+    pub date_reference: Option<u32>,    // as yyyymmdd
+    pub half_life: f64,
+}
+
+impl SgameWeight {
+    pub fn new() -> Self {
+        SgameWeight {
+            elo_threshold: None,
+            elo_points: 200.0,
+            date_reference: None,
+            half_life: 10.0,
+        }
+    }
+
+
+    // contributions()
+    // the contribution of the moves of white and black in the game
+
+    pub fn contributions(&self, pgn: &pgn::Spgn) -> [f64; 2] {
+        let mut contributions: [f64; 2] = [1.0, 1.0];
+
+        if let Some(threshold) = self.elo_threshold {
+            for (side, tag) in [(color::WHITE, "WhiteElo"), (color::BLACK, "BlackElo")].iter() {
+                let elo = pgn.get_tag(tag).and_then(|e| e.trim().parse::<i32>().ok()).unwrap_or(0);
+                if elo > threshold {
+                    contributions[*side] *= 2f64.powf((elo - threshold) as f64 / self.elo_points);
+                }
+            }
+        }
+
+        if let Some(reference) = self.date_reference {
+            let age = match pgn.get_tag("Date").and_then(|d| filter::parse_date(d, 0)) {
+                Some(date) => (date_in_years(reference) - date_in_years(date)).max(0.0),
+                None => self.half_life,
+            };
+            let decay = 0.5f64.powf(age / self.half_life);
+            contributions[color::WHITE] *= decay;
+            contributions[color::BLACK] *= decay;
+        }
+
+        contributions
+    }
+}
+
+
+// date_in_years()
+// the date yyyymmdd as years, the unknown month and day (0) are january and 1
+
+fn date_in_years(date: u32) -> f64 {
+    let year = (date / 10000) as f64;
+    let month = ((date / 100) % 100).clamp(1, 12) as f64;
+    let day = (date % 100).clamp(1, 31) as f64;
+    year + (month - 1.0) / 12.0 + (day - 1.0) / 365.0
+}
+
+
+
+// what makes two games the same for -dedup
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Dedup {
+    Moves,              // the moves
+    MovesAndPlayers,    // the moves, White, Black and Date
+}
+
+
+/* The games already inserted, by their fingerprints */
+#[derive(Clone)]
+pub struct Sfingerprints {
+    seen: HashSet<u64>,
+    pub duplicates: i32,
+}
+
+impl Sfingerprints {
+    pub fn new() -> Self {
+        Sfingerprints {
+            seen: HashSet::new(),
+            duplicates: 0,
+        }
+    }
+
+
+    // is_duplicate()
+    // the game has been seen before; the first time it is recorded
+
+    pub fn is_duplicate(&mut self, dedup: Dedup, pgn: &pgn::Spgn, game_moves: &[(String, i32, i32)]) -> bool {
+        if self.seen.insert(fingerprint(dedup, pgn, game_moves)) {
+            false
+        }
+        else {
+            self.duplicates += 1;
+            true
+        }
+    }
+}
+
+
+// fingerprint()
+// the hash of a game for -dedup: the moves without their annotations
+// (+, #, !, ?) and the castles with 0 as O; the names of the players
+// without case, spaces and punctuation
+
+pub fn fingerprint(dedup: Dedup, pgn: &pgn::Spgn, game_moves: &[(String, i32, i32)]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    for (san, _, _) in game_moves.iter() {
+        let san = san.trim_end_matches(['+', '#', '!', '?']);
+        match san {
+            "0-0" => "O-O".hash(&mut hasher),
+            "0-0-0" => "O-O-O".hash(&mut hasher),
+            _ => san.hash(&mut hasher),
+        }
+    }
+
+    if dedup == Dedup::MovesAndPlayers {
+        for tag in ["White", "Black"].iter() {
+            let name: String = pgn.get_tag(tag).unwrap_or("").chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(|c| c.to_lowercase())
+                .collect();
+            name.hash(&mut hasher);
+        }
+        pgn.get_tag("Date").and_then(|d| filter::parse_date(d, 0)).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
 
-pub fn make_new_book (pgn_file: &str, book_bin: &str, half_moves: i16) {
+// read_game_moves()
+// the moves of the game read by pgn_next_game(), with their line and column
+
+fn read_game_moves(pgn: &mut pgn::Spgn) -> Vec<(String, i32, i32)> {
+    let mut san: String = String::new();
+    let mut game_moves: Vec<(String, i32, i32)> = Vec::new();
+    while pgn.pgn_next_move(&mut san, 256) {
+        game_moves.push((san.clone(), pgn.move_line, pgn.move_column));
+    }
+    game_moves
+}
+
+
+
+/* The options of make-book */
+pub struct SmakeOptions {
+    pub pgn_files: Vec<(String, f64)>,  // with the multiplier of their games
+    pub bin_file: String,
+    pub max_plies: [i16; 2],        // the half-moves of white and black moves in the book
+    pub leaf_cutoff: Option<u32>,   // stop a game in a position of fewer games until now
+    pub min_games: Option<u32>,     // two passes: stop a game in a position of fewer games in all
+    pub parser: san::SsanParser,
+    pub encoding: pgn::SpgnEncoding,
+    pub filter: filter::SgameFilter,
+    pub opponent_moves: bool,       // with -player, also the moves of the opponents
+    pub game_weight: SgameWeight,
+    pub formula: book::WeightFormula,
+    pub keep_zero: bool,            // the moves of weight 0 are stored
+    pub learn_wdl: bool,            // the results packed in the learn field
+    pub stats_file: Option<String>, // the statistics of the moves, beside the book
+    pub update_file: Option<String>, // the games of previous runs, extended with the new ones
+    pub dedup: Option<Dedup>,       // only the first game of the ones that are the same
+    pub command: String,            // the command line, for the metadata
+}
+
+impl SmakeOptions {
+    pub fn new() -> Self {
+        SmakeOptions {
+            pgn_files: Vec::new(),
+            bin_file: String::from("book.bin"),
+            max_plies: [20, 20],
+            leaf_cutoff: None,
+            min_games: None,
+            parser: san::SsanParser::new(),
+            encoding: pgn::SpgnEncoding::new(),
+            filter: filter::SgameFilter::new(),
+            opponent_moves: false,
+            game_weight: SgameWeight::new(),
+            formula: book::WeightFormula::Polyglot,
+            keep_zero: false,
+            learn_wdl: false,
+            stats_file: None,
+            update_file: None,
+            dedup: None,
+            command: String::new(),
+        }
+    }
+
+
+    // add_pgn()
+    // file or file:multiplier (as correspondence.pgn:3), false if the 
+    // multiplier is not a positive number
+
+    pub fn add_pgn(&mut self, arg: &str) -> bool {
+        if let Some(colon) = arg.rfind(':') {
+            let multiplier = &arg[colon+1..];
+            // games:2024.pgn is a path, not a multiplier
+            if !multiplier.is_empty() && multiplier.chars().all(|c| c.is_ascii_digit() || c == '.') {
+                return match multiplier.parse::<f64>() {
+                    Ok(m) if m > 0.0 => {
+                        self.pgn_files.push((arg[..colon].to_string(), m));
+                        true
+                    },
+                    _ => false,
+                };
+            }
+        }
+        // the ':' of c:\games.pgn is not a multiplier
+        self.pgn_files.push((arg.to_string(), 1.0));
+        true
+    }
+}
+
+
+
+pub fn make_new_book (options: &mut SmakeOptions) {
     //let half_moves: i16 = 20;
     let mut book: book::Sbook = book::Sbook::new();
     let mut final_book: Vec<book::SfinalEntry> = Vec::new();
 
+    // with -update and without -pgn the book is only written again
+    if options.pgn_files.is_empty() && options.update_file.is_none() {
+        options.pgn_files.push((String::from("book.pgn"), 1.0));
+    }
+
+    // the fingerprints of -dedup of the games of -update
+    let mut games: i32 = 0;
+    let mut previous = Sfingerprints::new();
+    if let Some(update_file) = &options.update_file {
+        if std::path::Path::new(update_file).exists() {
+            println!("reading previous games ...");
+            games = load_update(&mut book, update_file.as_str(), options.dedup, &mut previous);
+            println!("previous games: {}", games);
+        }
+    }
+
+    // the first pass of -min-games
+    let mut counts: HashMap<u64, u32> = HashMap::new();
+    if options.min_games.is_some() {
+        println!("counting positions ...");
+        let mut fingerprints = previous.clone();
+        for (pgn_file, _) in options.pgn_files.clone().iter() {
+            count_positions(&mut counts, &mut fingerprints, options, pgn_file.as_str());
+        }
+        println!("different positions: {}", counts.len());
+    }
+
     println!("inserting games ...");
-    book_insert(pgn_file, &mut book, half_moves);
+    let mut fingerprints = previous;
+    // the games that have reached every position, for the leaf cutoff
+    let mut seen: HashMap<u64, u32> = HashMap::new();
+    for (pgn_file, multiplier) in options.pgn_files.clone().iter() {
+        if options.pgn_files.len() > 1 {
+            println!("file {} (x {})", pgn_file, multiplier);
+        }
+        games += book_insert(&mut book, options, &counts, &mut seen, &mut fingerprints, pgn_file.as_str(), *multiplier);
+    }
+
+    // before the moves of weight 0 are removed
+    if let Some(update_file) = &options.update_file {
+        println!("writing update file ...");
+        save_update(&book, update_file.as_str(), games, options.dedup, &fingerprints);
+    }
     println!("ending calculations ...");
-    book.do_calculations();
+    book.do_calculations(options.formula);
+    book.sort_entries(options.keep_zero);
     
+    let mut stats: Vec<book::SmoveStats> = Vec::new();
     for (_key, value) in book.btree.iter_mut() {
         for mov in value.iter() {
-            let tmp = book::SfinalEntry::new(mov.key, mov.move_, mov.weight, mov.learn);
+            let move_stats = mov.stats();
+            let learn = if options.learn_wdl { move_stats.learn() } else { mov.learn };
+            let tmp = book::SfinalEntry::new(mov.key, mov.move_, mov.weight, learn);
             final_book.push(tmp);
+            stats.push(move_stats);
         }
     }
     
     //let name: &str = "book.bin";
+    let book_bin: &str = options.bin_file.as_str();
     let file = File::create(book_bin);
 
     println!("writing book file ...");
@@ -41,29 +312,331 @@ pub fn make_new_book (pgn_file: &str, book_bin: &str, half_moves: i16) {
                 write_integer(&mut f,8,entry.key);
                 write_integer(&mut f,2,entry.move_.into());
                 write_integer(&mut f,2,entry.weight.into());
-                write_integer(&mut f,4,0);
+                write_integer(&mut f,4,entry.learn.into());
             }
         },
         Err(e) => panic!("error {} creating book file {}", e, book_bin),
     }
+
+    if let Some(stats_file) = &options.stats_file {
+        println!("writing statistics file ...");
+        write_stats(stats_file.as_str(), &stats);
+    }
+
+    write_metadata(options, games, fingerprints.duplicates, final_book.len());
+}
+
+
+
+// metadata_file()
+// the file with the metadata of a book: book.bin.meta
+
+pub fn metadata_file(book_bin: &str) -> String {
+    format!("{}.meta", book_bin)
+}
+
+
+// write_metadata()
+// how the book was made, to reproduce it
+
+fn write_metadata(options: &SmakeOptions, games: i32, duplicates: i32, entries: usize) {
+
+    let meta_file = metadata_file(options.bin_file.as_str());
+    let mut lines: Vec<String> = Vec::new();
+
+    lines.push(String::from("# rschessbook book metadata"));
+    if !options.command.is_empty() {
+        lines.push(format!("command={}", options.command));
+    }
+    for (pgn_file, multiplier) in options.pgn_files.iter() {
+        lines.push(format!("pgn={}:{}", pgn_file, multiplier));
+    }
+    lines.push(format!("weight={}", options.formula.name()));
+    if options.keep_zero {
+        lines.push(String::from("keep-zero=true"));
+    }
+    if options.learn_wdl {
+        lines.push(String::from("learn=wdl"));
+    }
+    if let Some(stats_file) = &options.stats_file {
+        lines.push(format!("stats={}", stats_file));
+    }
+    if let Some(update_file) = &options.update_file {
+        lines.push(format!("update={}", update_file));
+    }
+    if let Some(threshold) = options.game_weight.elo_threshold {
+        lines.push(format!("elo-weight={}:{}", threshold, options.game_weight.elo_points));
+    }
+    if let Some(reference) = options.game_weight.date_reference {
+        lines.push(format!("date-weight={:04}.{:02}.{:02}:{}", 
+            reference / 10000, (reference / 100) % 100, reference % 100, options.game_weight.half_life));
+    }
+    lines.push(format!("max-ply-white={}", options.max_plies[color::WHITE]));
+    lines.push(format!("max-ply-black={}", options.max_plies[color::BLACK]));
+    if let Some(cutoff) = options.leaf_cutoff {
+        lines.push(format!("leaf-cutoff={}", cutoff));
+    }
+    if let Some(min) = options.min_games {
+        lines.push(format!("min-games={}", min));
+    }
+    match options.dedup {
+        Some(Dedup::Moves) => lines.push(String::from("dedup=moves")),
+        Some(Dedup::MovesAndPlayers) => lines.push(String::from("dedup=players")),
+        None => (),
+    }
+    lines.push(format!("games={}", games));
+    if options.dedup.is_some() {
+        lines.push(format!("duplicates={}", duplicates));
+    }
+    lines.push(format!("entries={}", entries));
+    lines.push(String::new());
+
+    if let Err(e) = std::fs::write(&meta_file, lines.join("\n")) {
+        let tmp = format!("write_metadata(): error {} creating file {}\n", e, meta_file);
+        util::my_fatal(tmp.as_str());
+    }
+}
+
+
+// read_metadata()
+// the pairs key=value of the metadata of a book (empty if there is not)
+
+pub fn read_metadata(book_bin: &str) -> Vec<(String, String)> {
+
+    let text = std::fs::read_to_string(metadata_file(book_bin)).unwrap_or_default();
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, '=');
+            Some((parts.next()?.to_string(), parts.next()?.to_string()))
+        })
+        .collect()
+}
+
+
+
+// count_positions()
+// the first pass of -min-games: the games of a file that reach every 
+// position until the last ply of the book, once by game
+
+fn count_positions(counts: &mut HashMap<u64, u32>, fingerprints: &mut Sfingerprints, 
+        options: &mut SmakeOptions, file_name: &str) {
+
+    let mut pgn: pgn::Spgn = pgn::Spgn::new();
+    let mut board: board::Sboard = board::Sboard::new();
+    let mut positions: HashSet<u64> = HashSet::new();
+
+    let last_ply = options.max_plies[color::WHITE].max(options.max_plies[color::BLACK]);
+    let parser = &mut options.parser;
+
+    pgn.init_number_game(1);
+    pgn.encoding = options.encoding;
+    pgn.pgn_open(file_name);
+
+    while pgn.pgn_next_game() {
+        let game_moves = read_game_moves(&mut pgn);
+        if !options.filter.accepts(&pgn) {
+            continue;
+        }
+        if options.dedup.is_some_and(|dedup| fingerprints.is_duplicate(dedup, &pgn, &game_moves)) {
+            continue;
+        }
+        board.set_fen(util::START_FEN);
+        positions.clear();
+        positions.insert(board.hash_key);
+
+        let mut game_over = false;
+        for (count_moves, (san, move_line, move_column)) in game_moves.iter().enumerate() {
+            if count_moves < last_ply as usize && !game_over {
+                let mov = parser.parse(san.as_str(), &mut board);
+                if let Err(e) = &mov {
+                    let tmp = format!(
+                        "count_positions(): {} \"{}\" at line {}, column {},game {}\n",
+                        e, san, move_line, move_column, pgn.game_nb);
+                    util::my_fatal(tmp.as_str());
+                }
+                let mut mov = mov.unwrap();
+                if mov.tipe == moves::MOVE_TYPE_NONE {
+                    game_over = true;
+                }
+                else {
+                    board.make_move(&mut mov);
+                    positions.insert(board.hash_key);
+                    game_over = board.status().is_over();
+                }
+            }
+        }
+
+        for key in positions.iter() {
+            *counts.entry(*key).or_insert(0) += 1;
+        }
+    }
+}
+
+
+// write_stats()
+// the statistics of the moves in the order of the book, 26 bytes each 
+// in big endian: key 8, move 2, wins 4, draws 4, losses 4, elo 2, year 2
+
+fn write_stats(stats_file: &str, stats: &[book::SmoveStats]) {
+
+    match File::create(stats_file) {
+        Ok(mut f) => {
+            for entry in stats.iter() {
+                write_integer(&mut f,8,entry.key);
+                write_integer(&mut f,2,entry.move_.into());
+                write_integer(&mut f,4,entry.wins.into());
+                write_integer(&mut f,4,entry.draws.into());
+                write_integer(&mut f,4,entry.losses.into());
+                write_integer(&mut f,2,entry.elo.into());
+                write_integer(&mut f,2,entry.year.into());
+            }
+        },
+        Err(e) => {
+            let tmp = format!("write_stats(): error {} creating file {}\n", e, stats_file);
+            util::my_fatal(tmp.as_str());
+        },
+    }
+}
+
+
+// read_stats()
+// the statistics written by write_stats()
+
+pub fn read_stats(stats_file: &str) -> Vec<book::SmoveStats> {
+
+    let bytes = match std::fs::read(stats_file) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            let tmp = format!("read_stats(): error {} reading file {}\n", e, stats_file);
+            util::my_fatal(tmp.as_str());
+            Vec::new()
+        },
+    };
+    let number = |b: &[u8]| -> u64 { b.iter().fold(0, |n, &byte| (n << 8) | byte as u64) };
+
+    bytes.chunks_exact(26).map(|e| book::SmoveStats {
+        key: number(&e[0..8]),
+        move_: number(&e[8..10]) as u16,
+        wins: number(&e[10..14]) as u32,
+        draws: number(&e[14..18]) as u32,
+        losses: number(&e[18..22]) as u32,
+        elo: number(&e[22..24]) as u16,
+        year: number(&e[24..26]) as u16,
+    }).collect()
+}
+
+
+// the first bytes of the file of -update, before the number of games
+const UPDATE_MAGIC: &[u8; 8] = b"RSBKDB02";
+
+
+// dedup_code()
+// the -dedup of the file of -update: 0 without it
+
+fn dedup_code(dedup: Option<Dedup>) -> u8 {
+    match dedup {
+        None => 0,
+        Some(Dedup::Moves) => 1,
+        Some(Dedup::MovesAndPlayers) => 2,
+    }
+}
+
+
+// save_update()
+// the file of -update: the magic, the games (4 bytes), the -dedup (1 byte), 
+// the fingerprints of the games (4 bytes and 8 each one) and every entry 
+// of the book with the results of its games, not only the weight
+
+fn save_update(book: &book::Sbook, update_file: &str, games: i32, dedup: Option<Dedup>, 
+        fingerprints: &Sfingerprints) {
+
+    let mut bytes: Vec<u8> = Vec::new();
+    bytes.extend_from_slice(UPDATE_MAGIC);
+    bytes.extend_from_slice(&(games as u32).to_be_bytes());
+    bytes.push(dedup_code(dedup));
+    bytes.extend_from_slice(&(fingerprints.seen.len() as u32).to_be_bytes());
+    let mut seen: Vec<u64> = fingerprints.seen.iter().copied().collect();
+    seen.sort_unstable();
+    for fingerprint in seen.iter() {
+        bytes.extend_from_slice(&fingerprint.to_be_bytes());
+    }
+    for (_key, value) in book.btree.iter() {
+        for mov in value.iter() {
+            bytes.extend_from_slice(&mov.to_bytes());
+        }
+    }
+
+    if let Err(e) = std::fs::write(update_file, bytes) {
+        let tmp = format!("save_update(): error {} creating file {}\n", e, update_file);
+        util::my_fatal(tmp.as_str());
+    }
+}
+
+
+// load_update()
+// the entries of a file written by save_update() to the book and its 
+// fingerprints, the games; the file must have the same -dedup
+
+fn load_update(book: &mut book::Sbook, update_file: &str, dedup: Option<Dedup>, 
+        fingerprints: &mut Sfingerprints) -> i32 {
+
+    let bytes = std::fs::read(update_file).unwrap_or_default();
+    let number = |b: &[u8]| -> u64 { b.iter().fold(0, |n, &byte| (n << 8) | byte as u64) };
+    let start = UPDATE_MAGIC.len() + 9;
+    let header = if bytes.len() >= start { start + 8 * number(&bytes[start-4..start]) as usize } else { start };
+    if bytes.len() < header || &bytes[..UPDATE_MAGIC.len()] != UPDATE_MAGIC || 
+            !(bytes.len() - header).is_multiple_of(book::DB_ENTRY_SIZE) {
+        let tmp = format!("load_update(): {} is not an update file of make-book\n", update_file);
+        util::my_fatal(tmp.as_str());
+    }
+    if bytes[12] != dedup_code(dedup) {
+        let tmp = format!("load_update(): {} was not made with the same -dedup or -dedup-players\n", update_file);
+        util::my_fatal(tmp.as_str());
+    }
+
+    for fingerprint in bytes[start..header].chunks_exact(8) {
+        fingerprints.seen.insert(number(fingerprint));
+    }
+    for entry in bytes[header..].chunks_exact(book::DB_ENTRY_SIZE) {
+        book.append_entry(book::Sentry::from_bytes(entry));
+    }
+    number(&bytes[8..12]) as i32
+}
+
+
+// player_elos()
+// the Elo of white and black, 0 if unknown
+
+fn player_elos(pgn: &pgn::Spgn) -> [i32; 2] {
+    let elo = |tag: &str| pgn.get_tag(tag).and_then(|e| e.trim().parse::<i32>().ok()).unwrap_or(0).max(0);
+    [elo("WhiteElo"), elo("BlackElo")]
 }
 
 
 
 // book_insert()
+// the games of a file, their contributions by the multiplier; with 
+// -min-games the counts are the ones of the first pass, and seen are the 
+// games of all the files until now for -leaf-cutoff
 
-fn book_insert(file_name: &str, book: &mut book::Sbook, half_moves: i16) {
+fn book_insert(book: &mut book::Sbook, options: &mut SmakeOptions, counts: &HashMap<u64, u32>, 
+        seen: &mut HashMap<u64, u32>, fingerprints: &mut Sfingerprints, file_name: &str, multiplier: f64) -> i32 {
 
     let mut pgn: pgn::Spgn = pgn::Spgn::new();
     let mut board: board::Sboard = board::Sboard::new();
     
     let mut ply: i32;
     let mut result: i32;
-    let mut san: String = String::new();    //from("");
     //let mut move_ : i32;
     //let mut pos: i32;
     let mut count_moves: i16;
 
+    let max_plies = options.max_plies;
+    let last_ply = max_plies[color::WHITE].max(max_plies[color::BLACK]);
+    let mut positions: HashSet<u64> = HashSet::new();
+    let parser = &mut options.parser;
+
     assert_ne!(file_name.len(), 0);
 
     // init
@@ -72,13 +645,29 @@ fn book_insert(file_name: &str, book: &mut book::Sbook, half_moves: i16) {
     let now = Instant::now();
 
     pgn.init_number_game(1);
+    pgn.encoding = options.encoding;
     // scan loop
 
     pgn.pgn_open(file_name);
 
     let mut contador: i32 = 0;
+    let mut filtered: i32 = 0;
+    let duplicates = fingerprints.duplicates;
     while pgn.pgn_next_game() {
+        let game_moves = read_game_moves(&mut pgn);
+        if !options.filter.accepts(&pgn) {
+            filtered += 1;
+            continue;
+        }
+        if options.dedup.is_some_and(|dedup| fingerprints.is_duplicate(dedup, &pgn, &game_moves)) {
+            continue;
+        }
         board.set_fen(util::START_FEN);
+        positions.clear();
+        let player_side = options.filter.player_side(&pgn);
+        let contributions = options.game_weight.contributions(&pgn);
+        let elos = player_elos(&pgn);
+        let year = pgn.get_tag("Date").and_then(|d| filter::parse_date(d, 0)).map_or(0, |d| d / 10000);
 
         ply = 0;
         result = 0;
@@ -98,26 +687,66 @@ fn book_insert(file_name: &str, book: &mut book::Sbook, half_moves: i16) {
         }
 
         count_moves = 0;
-        while pgn.pgn_next_move(&mut san, 256) {
-            if ply < max_ply {
-                
-                if count_moves <= half_moves {
-                    let san1 = san.clone();
-                
-                    let mov = san::move_from_san(san1.to_string(), &mut board);
-                    if mov.is_none() {
-                        let tmp = format!(
-                            "book_insert(): illegal move \"{}\" at line {}, column {},game {}\n",
-                            san, pgn.move_line, pgn.move_column, pgn.game_nb);
-                        util::my_fatal(tmp.as_str());
+        let mut game_over = false;
+        let mut leaf_reached = false;
+        for (san, move_line, move_column) in game_moves.iter() {
+            if ply < max_ply && !game_over && count_moves < last_ply {
+                let san1 = san.clone();
+            
+                let mov = parser.parse(san1.as_str(), &mut board);
+                if let Err(e) = &mov {
+                    let tmp = format!(
+                        "book_insert(): {} \"{}\" at line {}, column {},game {}\n",
+                        e, san, move_line, move_column, pgn.game_nb);
+                    util::my_fatal(tmp.as_str());
+                }
+
+                let mut mov = mov.unwrap();
+                if mov.tipe == moves::MOVE_TYPE_NONE {
+                    // after a null move the positions are not from the game
+                    game_over = true;
+                    count_moves += 1;
+                    continue;
+                }
+                let hash = mov.hash;
+                let mov_u16 = mov.encoded_move;
+                let mover = board.side;
+
+                // a position of few games is the end of the line, but the
+                // next positions of the game are counted
+                if let Some(cutoff) = options.leaf_cutoff {
+                    // once by game, even if the position is repeated
+                    let games = seen.entry(hash).or_insert(0);
+                    if positions.insert(hash) {
+                        *games += 1;
+                    }
+                    if *games < cutoff {
+                        leaf_reached = true;
                     }
+                }
 
-                    let hash = mov.clone().unwrap().hash;
-                    let mov_u16 = mov.clone().unwrap().encoded_move;
-                    board.make_move(&mut mov.unwrap());
+                board.make_move(&mut mov);
 
-                    book.insert_move(hash, mov_u16, result);
-                }              
+                // the move stays in the book if it goes from and to positions
+                // of min_games games (the last move of a game goes to a leaf
+                // only if other games have reached it)
+                if let Some(min) = options.min_games {
+                    let games = |key: u64| counts.get(&key).copied().unwrap_or(0);
+                    if games(hash) < min || games(board.hash_key) < min {
+                        leaf_reached = true;
+                    }
+                }
+
+                if count_moves < max_plies[mover] && !leaf_reached &&
+                        (player_side.is_none_or(|side| side == mover) || options.opponent_moves) {
+                    // the result for the side that moves
+                    let resul = if mover == color::WHITE { result } else { -result };
+                    book.insert_move(hash, mov_u16, resul, contributions[mover] * multiplier, 
+                        elos[mover], year);
+                }
+
+                // no more book moves after a mate, stalemate or draw
+                game_over = board.status().is_over();
             }
             count_moves += 1;
         }
@@ -125,8 +754,15 @@ fn book_insert(file_name: &str, book: &mut book::Sbook, half_moves: i16) {
         if contador % 500 == 0 { println!("until now {} games processed", contador); }
     }
     println!("total processed games: {}", contador);
+    if filtered > 0 {
+        println!("games skipped by the filters: {}", filtered);
+    }
+    if fingerprints.duplicates > duplicates {
+        println!("duplicated games skipped: {}", fingerprints.duplicates - duplicates);
+    }
     time = now.elapsed().as_secs().into();    // it throws u128
     println!("Time used: {}", time);
+    contador
 }
 
 
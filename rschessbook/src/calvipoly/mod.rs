@@ -12,92 +12,1165 @@ mod san;
 mod pgn;
 mod book;
 mod make_book;
+mod pgn_check;
+mod filter;
+mod dump_book;
+mod book_pgn;
+mod book_dot;
+mod book_stats;
+mod book_check;
+mod book_prune;
+mod book_edit;
 
 
 const HELP_MESSAGE: &str = r#"
 SYNTAX
-* rschessbook make-book [-pgn inputfile] [-bin outputfile] [-max-ply ply]
+* rschessbook make-book [-pgn inputfile[:N]] [-bin outputfile] [-max-ply ply]
+*                        [-pieces lang] [-encoding enc] [-fallback enc]
 *
+* -pgn can be repeated, and the games of a file count N times (3, 0.5 ...)
+* if -pgn is omitted then the file book.pgn will be read
 * if -bin parameter is omitted then the name book.bin will be created
 * if -max-ply is omitted then 20 half-moves will be assigned
+* [-max-ply-white ply] [-max-ply-black ply] are the half-moves for the moves 
+*    of one color (a deeper white repertoire), they change the one of -max-ply
+* [-leaf-cutoff N] ends a game in the first position reached by fewer
+*    than N games until then (the tails of unique lines are not stored)
+* [-min-games N] reads the games twice: the first time counts the games of
+*    every position, the second ends a game in the first move from or to
+*    a position of fewer than N games in all (every leaf has N games)
+* -pieces are the letters of the pieces in the moves: en (the default), 
+*    es, de, fr, it, figurine, or auto to detect them
+* -encoding of the pgn file: utf-8 (the default), latin-1 or windows-1252
+* -fallback is the encoding of the bytes that are not utf-8: 
+*    windows-1252 (the default) or latin-1
+* a file with the utf-8 BOM is always read as utf-8
+*
+* the games can be filtered by their tags (the games without the tag are skipped):
+*   [-min-elo N] [-min-elo-both N]   one or both players with Elo N or more
+*   [-date-from yyyy.mm.dd] [-date-to yyyy.mm.dd]   the month and day are optional
+*   [-event text] [-site text]   the tag has the text (case insensitive)
+*   [-time-control list]   as "blitz,rapid", from ultrabullet, bullet, blitz,
+*                          rapid, classical, correspondence
+*   [-only-finished]   skip the games with result "*"
+*   [-all-variants]   also the games of Variant tags other than standard
+*
+* a repertoire book has only the moves of a player:
+*   [-player name]   "Carlsen, Magnus", "Magnus Carlsen" or "Carlsen" are the same
+*   [-color white|black]   only the games of the player with that color
+*   [-opponent-moves]   also the moves of the opponents
+*
+* the contribution of a game to the weights can depend on:
+*   [-elo-weight N[:points]]   the Elo of the side that moves, doubling every
+*                              points (200 if omitted) above N
+*   [-date-weight yyyy.mm.dd[:years]]   the age of the game at that date, halving 
+*                              every years (10 if omitted); the games without 
+*                              date count as if they had that age
+*
+* [-weight formula] is how the weight of a move is calculated from its games:
+*   polyglot (2*wins + draws, the default), frequency, score (score percentage
+*   * percentage of the games of the position, from 0 to 10000), wins, 3-1-0 
+*   (3*wins + draws), wilson (lower bound of the score), uniform (all the 
+*   moves the same)
+* the moves of every position are sorted by descending weight, and the
+*    moves of weight 0 are not stored unless [-keep-zero]
+* the formula and the options are recorded in the file outputfile.meta
+*
+* the statistics of every move (games won, drawn and lost by the side that
+* moves, average Elo of the players and last year it was played):
+*   [-learn wdl]   the results in the learn field of the entries, as
+*                  wins << 20 | draws << 10 | losses (10 bits each, scaled)
+*   [-stats file]   all of them in a file, entry by entry as in the book
+*
+* [-dedup] counts only once the games with the same moves (the checks, 
+*    annotations and 0-0 or O-O are not compared), in all the pgn files
+* [-dedup-players] the same, if they also have the same White, Black and Date
+* [-update file] keeps the games of the book in the file: it is read (if it
+*    exists), the games of -pgn are added and it is written with the book;
+*    without -pgn the book is only written again (with other -weight ...).
+*    The filters, limits and -min-games only apply to the new games; the
+*    fingerprints of -dedup are kept in the file, and it must always be
+*    used with the same -dedup or -dedup-players
+*
+* rschessbook check-pgn [-pgn inputfile] [-strict] [-pieces lang]
+*                       [-encoding enc] [-fallback enc]
+*
+* reports the games with illegal moves or whose result contradicts
+* the final position (checkmate, stalemate, insufficient material)
+* -strict only accepts standard SAN (no 0-0, e8Q, Ng1-f3, e2e4, --)
+*
+* rschessbook dump-book [-bin inputfile] [-format text|csv|json]
+*
+* lists every entry of the book: key (hex), move (uci), weight, learn, and
+* the move in SAN and the FEN of the position if it is reached from the
+* initial position by the moves of the book
+* if -bin is omitted then the file book.bin will be read
+* if -format is omitted then the entries will be written as text
+*
+* rschessbook book2pgn [-bin inputfile] [-fen position] [-max-ply N]
+*                      [-pgn outputfile]
+*
+* writes the book as one game: the move of more weight is the main line and
+* the others are variations, with their weight and percentage in comments;
+* a position already written (a transposition) is not written again
+* if -bin is omitted then the file book.bin will be read
+* if -fen is omitted then the initial position will be used
+* if -max-ply is omitted then all the moves of the book will be written
+* if -pgn is omitted then the game will be written in the standard output
+*
+* rschessbook book2dot [-bin inputfile] [-fen position] [-max-ply N]
+*                      [-min-share P] [-label path|fen] [-dot outputfile]
+*
+* writes the tree of the book as a Graphviz graph: the positions are the
+* nodes, once even if several lines reach them, and the moves are the
+* edges with their weight and the percentage of the weights of the position
+* if -bin is omitted then the file book.bin will be read
+* if -fen is omitted then the initial position will be used
+* if -max-ply is omitted then 6 half-moves will be drawn
+* -min-share skips the moves of less than P percent of their position
+* -label fen writes the position instead of the moves that reach it
+* if -dot is omitted then the graph will be written in the standard output
+*
+* rschessbook book-stats [-bin inputfile]
+*
+* reports the shape of the book: entries, positions, moves per position,
+* the distribution of the weights, the entries by ply reached from the
+* initial position, the unreachable entries, the illegal moves and the
+* repeated (key, move) pairs
+* if -bin is omitted then the file book.bin will be read
+*
+* rschessbook check-book [-bin inputfile]
+*
+* verifies that the size of the book is a multiple of 16 bytes, that the
+* entries are sorted by key, that no (key, move) is repeated and that every
+* move is legal in a position reached from the initial one by the book
+* if -bin is omitted then the file book.bin will be read
+* the exit code is 1 if the book has errors
+*
+* rschessbook repair-book [-bin inputfile] -out outputfile
+*
+* writes the book sorted, with the weights of the repeated (key, move)
+* added, and without the unreachable entries and the illegal moves
+* if -bin is omitted then the file book.bin will be read
+*
+* rschessbook prune-book [-bin inputfile] -out outputfile [-roots fenfile]
+*                        [-max-ply N] [-min-share P]
+*
+* keeps only the entries of the positions reached from the initial position
+* by the moves of the book, breadth first
+* if -bin is omitted then the file book.bin will be read
+* -roots adds the positions of a file with a FEN by line as starting points
+* -max-ply keeps the positions less than N half-moves from a starting point
+* -min-share drops and does not follow the moves of less than P percent
+* of the weights of their position
+*
+* rschessbook edit-book add|remove|set-weight|forbid [-bin inputfile]
+*                       [-fen position] -move move [-weight N] [-out outputfile]
+*
+* changes one move of a position (given in SAN or uci) and saves the book
+* sorted: add puts it with -weight (1 if omitted), remove takes it out,
+* set-weight changes its weight and forbid removes the move of the same
+* piece from every position of the book reached from the initial position;
+* the positions only reached by a removed move stay in the book until 
+* prune-book
+* if -bin is omitted then the file book.bin will be read
+* if -fen is omitted then the initial position will be used
+* if -out is omitted then the input book will be rewritten
+*
+* rschessbook perft [-fen position] [-depth N] [-divide] [-threads N] [-hash MB]
+*
+* if -fen is omitted then the initial position will be used
+* if -depth is omitted then 4 half-moves will be assigned
+* -divide shows the nodes below every move of the position
+* -threads shares the moves of the position among N threads
+* -hash keeps the counted positions in a table of MB megabytes
+* -divide can't be used with -threads or -hash
+*
+* rschessbook perft-suite -epd inputfile [-depth N]
+*
+* the lines of the epd file have the node counts as ";D1 20 ;D2 400 ..."
+* if -depth is omitted then the depths until 6 will be verified
+* the exit code is 1 if a position fails
 "#;
 
 pub fn my_main(args: Vec<String>) {
 
-    if args.len() < 2 {
-        println!("{}",HELP_MESSAGE);
-        std::process::exit(util::EXIT_SUCCESS);
+    if args.len() < 2 {
+        println!("{}",HELP_MESSAGE);
+        std::process::exit(util::EXIT_SUCCESS);
+    }
+
+    if args.len() == 2 && (
+            &args[1] == "help"   || 
+            &args[1] == "-help"  || 
+            &args[1] == "--help" ||  
+            &args[1] == "-h"     ||  
+            &args[1] == "/?" ) {
+        println!("{}",HELP_MESSAGE);
+        std::process::exit(util::EXIT_SUCCESS);
+    }
+
+    if args.len() >= 2 && args[1] =="make-book" {
+        check_args(args.len(), args);
+    }
+    else if args.len() >= 2 && args[1] == "check-pgn" {
+        check_args_pgn(args.len(), args);
+    }
+    else if args.len() >= 2 && args[1] == "dump-book" {
+        check_args_dump(args.len(), args);
+    }
+    else if args.len() >= 2 && args[1] == "book2pgn" {
+        check_args_book2pgn(args.len(), args);
+    }
+    else if args.len() >= 2 && args[1] == "book2dot" {
+        check_args_book2dot(args.len(), args);
+    }
+    else if args.len() >= 2 && args[1] == "book-stats" {
+        check_args_book_stats(args.len(), args);
+    }
+    else if args.len() >= 2 && (args[1] == "check-book" || args[1] == "repair-book") {
+        check_args_check_book(args.len(), args);
+    }
+    else if args.len() >= 2 && args[1] == "prune-book" {
+        check_args_prune_book(args.len(), args);
+    }
+    else if args.len() >= 2 && args[1] == "edit-book" {
+        check_args_edit_book(args.len(), args);
+    }
+    else if args.len() >= 2 && args[1] == "perft" {
+        check_args_perft(args.len(), args);
+    }
+    else if args.len() >= 2 && args[1] == "perft-suite" {
+        check_args_perft_suite(args.len(), args);
+    }
+}
+
+
+pub fn check_args(argc: usize, argv: Vec<String>) {
+
+    let mut i: usize = 0;
+    let mut options = make_book::SmakeOptions::new();
+    options.command = argv.join(" ");
+
+    while i < argc {
+        if i == 0 {
+            //pass
+        }
+        else if argv[i] == "make-book"{
+            // skip
+        }
+        else if argv[i] == "-pgn" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -pgn : missing argument\n");
+            }
+            if !options.add_pgn(argv[i].as_str()) {
+                let tmp = format!("book_make() -pgn : the multiplier of \"{}\" must be a positive number\n", argv[i]);
+                util::my_fatal(tmp.as_str());
+            }
+        }
+        else if argv[i] == "-bin" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -bin : missing argument\n");
+            }
+            options.bin_file = argv[i].clone();
+        }
+        else if argv[i] == "-max-ply" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -max-ply : missing argument\n");
+            }
+            let res = argv[i].parse::<i16>();
+            if let Ok(half_moves) = res {
+                options.max_plies = [half_moves, half_moves];
+            }
+            else {
+                util::my_fatal("book_make() -max-ply : numerical argument\n");
+            }
+            assert!(options.max_plies[color::WHITE] >= 0);
+        }
+        else if argv[i] == "-max-ply-white" || argv[i] == "-max-ply-black" {
+            let side = if argv[i] == "-max-ply-white" { color::WHITE } else { color::BLACK };
+            let option = argv[i].clone();
+            i += 1;
+            if i >= argc {
+                let tmp = format!("book_make() {} : missing argument\n", option);
+                util::my_fatal(tmp.as_str());
+            }
+            match argv[i].parse::<i16>() {
+                Ok(half_moves) if half_moves >= 0 => options.max_plies[side] = half_moves,
+                _ => {
+                    let tmp = format!("book_make() {} : numerical argument\n", option);
+                    util::my_fatal(tmp.as_str());
+                },
+            }
+        }
+        else if argv[i] == "-leaf-cutoff" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -leaf-cutoff : missing argument\n");
+            }
+            options.leaf_cutoff = Some(parse_positive("book_make() -leaf-cutoff : numerical argument\n", &argv[i]) as u32);
+        }
+        else if argv[i] == "-min-games" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -min-games : missing argument\n");
+            }
+            options.min_games = Some(parse_positive("book_make() -min-games : numerical argument\n", &argv[i]) as u32);
+        }
+        else if argv[i] == "-pieces" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -pieces : missing argument\n");
+            }
+            if !options.parser.set_pieces(argv[i].as_str()) {
+                let tmp = format!("book_make() -pieces : unknown language \"{}\"\n", argv[i]);
+                util::my_fatal(tmp.as_str());
+            }
+        }
+        else if argv[i] == "-encoding" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -encoding : missing argument\n");
+            }
+            if !options.encoding.set_encoding(argv[i].as_str()) {
+                let tmp = format!("book_make() -encoding : unknown encoding \"{}\"\n", argv[i]);
+                util::my_fatal(tmp.as_str());
+            }
+        }
+        else if argv[i] == "-fallback" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -fallback : missing argument\n");
+            }
+            if !options.encoding.set_fallback(argv[i].as_str()) {
+                let tmp = format!("book_make() -fallback : \"{}\" is not latin-1 or windows-1252\n", argv[i]);
+                util::my_fatal(tmp.as_str());
+            }
+        }
+        else if argv[i] == "-min-elo" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -min-elo : missing argument\n");
+            }
+            options.filter.min_elo = Some(parse_positive("book_make() -min-elo : numerical argument\n", &argv[i]));
+        }
+        else if argv[i] == "-min-elo-both" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -min-elo-both : missing argument\n");
+            }
+            options.filter.min_elo_both = Some(parse_positive("book_make() -min-elo-both : numerical argument\n", &argv[i]));
+        }
+        else if argv[i] == "-date-from" || argv[i] == "-date-to" {
+            let option = argv[i].clone();
+            i += 1;
+            if i >= argc {
+                let tmp = format!("book_make() {} : missing argument\n", option);
+                util::my_fatal(tmp.as_str());
+            }
+            let valid = if option == "-date-from" {
+                options.filter.set_date_from(argv[i].as_str())
+            }
+            else {
+                options.filter.set_date_to(argv[i].as_str())
+            };
+            if !valid {
+                let tmp = format!("book_make() {} : the date must be yyyy[.mm[.dd]]\n", option);
+                util::my_fatal(tmp.as_str());
+            }
+        }
+        else if argv[i] == "-event" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -event : missing argument\n");
+            }
+            options.filter.event = Some(argv[i].to_lowercase());
+        }
+        else if argv[i] == "-site" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -site : missing argument\n");
+            }
+            options.filter.site = Some(argv[i].to_lowercase());
+        }
+        else if argv[i] == "-time-control" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -time-control : missing argument\n");
+            }
+            if !options.filter.set_time_controls(argv[i].as_str()) {
+                let tmp = format!("book_make() -time-control : unknown category in \"{}\"\n", argv[i]);
+                util::my_fatal(tmp.as_str());
+            }
+        }
+        else if argv[i] == "-all-variants" {
+            options.filter.only_standard = false;
+        }
+        else if argv[i] == "-only-finished" {
+            options.filter.only_finished = true;
+        }
+        else if argv[i] == "-player" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -player : missing argument\n");
+            }
+            options.filter.player = Some(argv[i].clone());
+        }
+        else if argv[i] == "-color" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -color : missing argument\n");
+            }
+            options.filter.player_color = match argv[i].as_str() {
+                "white" => Some(color::WHITE),
+                "black" => Some(color::BLACK),
+                _ => {
+                    util::my_fatal("book_make() -color : white or black\n");
+                    None
+                },
+            };
+        }
+        else if argv[i] == "-opponent-moves" {
+            options.opponent_moves = true;
+        }
+        else if argv[i] == "-weight" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -weight : missing argument\n");
+            }
+            match book::WeightFormula::from_name(argv[i].as_str()) {
+                Some(formula) => options.formula = formula,
+                None => {
+                    let tmp = format!("book_make() -weight : unknown formula \"{}\"\n", argv[i]);
+                    util::my_fatal(tmp.as_str());
+                },
+            }
+        }
+        else if argv[i] == "-keep-zero" {
+            options.keep_zero = true;
+        }
+        else if argv[i] == "-learn" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -learn : missing argument\n");
+            }
+            match argv[i].as_str() {
+                "wdl" => options.learn_wdl = true,
+                "none" => options.learn_wdl = false,
+                _ => {
+                    let tmp = format!("book_make() -learn : unknown format \"{}\"\n", argv[i]);
+                    util::my_fatal(tmp.as_str());
+                },
+            }
+        }
+        else if argv[i] == "-dedup" {
+            options.dedup = Some(make_book::Dedup::Moves);
+        }
+        else if argv[i] == "-dedup-players" {
+            options.dedup = Some(make_book::Dedup::MovesAndPlayers);
+        }
+        else if argv[i] == "-update" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -update : missing argument\n");
+            }
+            options.update_file = Some(argv[i].clone());
+        }
+        else if argv[i] == "-stats" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -stats : missing argument\n");
+            }
+            options.stats_file = Some(argv[i].clone());
+        }
+        else if argv[i] == "-elo-weight" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -elo-weight : missing argument\n");
+            }
+            let error = "book_make() -elo-weight : the argument must be elo[:points]\n";
+            let mut parts = argv[i].splitn(2, ':');
+            options.game_weight.elo_threshold = Some(parse_positive(error, parts.next().unwrap()));
+            if let Some(points) = parts.next() {
+                options.game_weight.elo_points = parse_positive(error, points) as f64;
+            }
+        }
+        else if argv[i] == "-date-weight" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_make() -date-weight : missing argument\n");
+            }
+            let error = "book_make() -date-weight : the argument must be yyyy.mm.dd[:years]\n";
+            let mut parts = argv[i].splitn(2, ':');
+            options.game_weight.date_reference = filter::parse_date(parts.next().unwrap(), 0);
+            if options.game_weight.date_reference.is_none() {
+                util::my_fatal(error);
+            }
+            if let Some(years) = parts.next() {
+                match years.parse::<f64>() {
+                    Ok(years) if years > 0.0 => options.game_weight.half_life = years,
+                    _ => util::my_fatal(error),
+                }
+            }
+        }
+        else {
+            let tmp = format!("book_make(): unknown option \"{}\"\n",argv[i]);
+            util::my_fatal(tmp.as_str());
+        }
+        i += 1;
+    }
+
+    if options.filter.player_color.is_some() && options.filter.player.is_none() {
+        util::my_fatal("book_make() -color : needs -player\n");
+    }
+
+    make_book::make_new_book(&mut options);
+}
+
+
+pub fn check_args_pgn(argc: usize, argv: Vec<String>) {
+
+    let mut i: usize = 0;
+    let mut pgn_file: &str;
+    let mut strict: bool = false;
+    let mut pieces: &str = "en";
+    let mut encoding = pgn::SpgnEncoding::new();
+
+    pgn_file = "book.pgn";
+
+    while i < argc {
+        if i == 0 {
+            //pass
+        }
+        else if argv[i] == "check-pgn" {
+            // skip
+        }
+        else if argv[i] == "-pgn" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("check_pgn() -pgn : missing argument\n");
+            }
+            pgn_file = argv[i].as_str();
+        }
+        else if argv[i] == "-strict" {
+            strict = true;
+        }
+        else if argv[i] == "-pieces" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("check_pgn() -pieces : missing argument\n");
+            }
+            pieces = argv[i].as_str();
+        }
+        else if argv[i] == "-encoding" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("check_pgn() -encoding : missing argument\n");
+            }
+            if !encoding.set_encoding(argv[i].as_str()) {
+                let tmp = format!("check_pgn() -encoding : unknown encoding \"{}\"\n", argv[i]);
+                util::my_fatal(tmp.as_str());
+            }
+        }
+        else if argv[i] == "-fallback" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("check_pgn() -fallback : missing argument\n");
+            }
+            if !encoding.set_fallback(argv[i].as_str()) {
+                let tmp = format!("check_pgn() -fallback : \"{}\" is not latin-1 or windows-1252\n", argv[i]);
+                util::my_fatal(tmp.as_str());
+            }
+        }
+        else {
+            let tmp = format!("check_pgn(): unknown option \"{}\"\n",argv[i]);
+            util::my_fatal(tmp.as_str());
+        }
+        i += 1;
+    }
+
+    let mut parser = if strict { san::SsanParser::strict() } else { san::SsanParser::new() };
+    if !parser.set_pieces(pieces) {
+        let tmp = format!("check_pgn() -pieces : unknown language \"{}\"\n", pieces);
+        util::my_fatal(tmp.as_str());
+    }
+    pgn_check::check_pgn(pgn_file, parser, encoding);
+}
+
+
+pub fn check_args_dump(argc: usize, argv: Vec<String>) {
+
+    let mut i: usize = 0;
+    let mut bin_file: &str = "book.bin";
+    let mut format = dump_book::DumpFormat::Text;
+
+    while i < argc {
+        if i == 0 {
+            //pass
+        }
+        else if argv[i] == "dump-book" {
+            // skip
+        }
+        else if argv[i] == "-bin" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("dump_book() -bin : missing argument\n");
+            }
+            bin_file = argv[i].as_str();
+        }
+        else if argv[i] == "-format" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("dump_book() -format : missing argument\n");
+            }
+            match dump_book::DumpFormat::from_name(argv[i].as_str()) {
+                Some(f) => format = f,
+                None => {
+                    let tmp = format!("dump_book() -format : unknown format \"{}\"\n", argv[i]);
+                    util::my_fatal(tmp.as_str());
+                },
+            }
+        }
+        else {
+            let tmp = format!("dump_book(): unknown option \"{}\"\n",argv[i]);
+            util::my_fatal(tmp.as_str());
+        }
+        i += 1;
+    }
+
+    dump_book::dump_book(bin_file, format);
+}
+
+
+pub fn check_args_book2pgn(argc: usize, argv: Vec<String>) {
+
+    let mut i: usize = 0;
+    let mut bin_file: &str = "book.bin";
+    let mut fen: &str = util::START_FEN;
+    let mut max_ply: usize = usize::MAX;
+    let mut pgn_file: Option<&str> = None;
+
+    while i < argc {
+        if i == 0 {
+            //pass
+        }
+        else if argv[i] == "book2pgn" {
+            // skip
+        }
+        else if argv[i] == "-bin" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book2pgn() -bin : missing argument\n");
+            }
+            bin_file = argv[i].as_str();
+        }
+        else if argv[i] == "-fen" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book2pgn() -fen : missing argument\n");
+            }
+            fen = argv[i].as_str();
+        }
+        else if argv[i] == "-max-ply" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book2pgn() -max-ply : missing argument\n");
+            }
+            max_ply = parse_positive("book2pgn() -max-ply : numerical argument\n", &argv[i]) as usize;
+        }
+        else if argv[i] == "-pgn" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book2pgn() -pgn : missing argument\n");
+            }
+            pgn_file = Some(argv[i].as_str());
+        }
+        else {
+            let tmp = format!("book2pgn(): unknown option \"{}\"\n",argv[i]);
+            util::my_fatal(tmp.as_str());
+        }
+        i += 1;
+    }
+
+    book_pgn::book2pgn(bin_file, fen, max_ply, pgn_file);
+}
+
+
+pub fn check_args_book2dot(argc: usize, argv: Vec<String>) {
+
+    let mut i: usize = 0;
+    let mut bin_file: &str = "book.bin";
+    let mut options = book_dot::SdotOptions::new();
+    let mut dot_file: Option<&str> = None;
+
+    while i < argc {
+        if i == 0 {
+            //pass
+        }
+        else if argv[i] == "book2dot" {
+            // skip
+        }
+        else if argv[i] == "-bin" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book2dot() -bin : missing argument\n");
+            }
+            bin_file = argv[i].as_str();
+        }
+        else if argv[i] == "-fen" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book2dot() -fen : missing argument\n");
+            }
+            options.fen = argv[i].clone();
+        }
+        else if argv[i] == "-max-ply" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book2dot() -max-ply : missing argument\n");
+            }
+            options.max_ply = parse_positive("book2dot() -max-ply : numerical argument\n", &argv[i]) as usize;
+        }
+        else if argv[i] == "-min-share" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book2dot() -min-share : missing argument\n");
+            }
+            match argv[i].parse::<f64>() {
+                Ok(share) if (0.0..=100.0).contains(&share) => options.min_share = share,
+                _ => util::my_fatal("book2dot() -min-share : percentage between 0 and 100\n"),
+            }
+        }
+        else if argv[i] == "-label" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book2dot() -label : missing argument\n");
+            }
+            match book_dot::DotLabel::from_name(argv[i].as_str()) {
+                Some(label) => options.label = label,
+                None => {
+                    let tmp = format!("book2dot() -label : unknown label \"{}\"\n", argv[i]);
+                    util::my_fatal(tmp.as_str());
+                },
+            }
+        }
+        else if argv[i] == "-dot" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book2dot() -dot : missing argument\n");
+            }
+            dot_file = Some(argv[i].as_str());
+        }
+        else {
+            let tmp = format!("book2dot(): unknown option \"{}\"\n",argv[i]);
+            util::my_fatal(tmp.as_str());
+        }
+        i += 1;
+    }
+
+    book_dot::book2dot(bin_file, &options, dot_file);
+}
+
+
+pub fn check_args_book_stats(argc: usize, argv: Vec<String>) {
+
+    let mut i: usize = 0;
+    let mut bin_file: &str = "book.bin";
+
+    while i < argc {
+        if i == 0 {
+            //pass
+        }
+        else if argv[i] == "book-stats" {
+            // skip
+        }
+        else if argv[i] == "-bin" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("book_stats() -bin : missing argument\n");
+            }
+            bin_file = argv[i].as_str();
+        }
+        else {
+            let tmp = format!("book_stats(): unknown option \"{}\"\n",argv[i]);
+            util::my_fatal(tmp.as_str());
+        }
+        i += 1;
+    }
+
+    book_stats::book_stats(bin_file);
+}
+
+
+pub fn check_args_check_book(argc: usize, argv: Vec<String>) {
+
+    let mut i: usize = 0;
+    let mut bin_file: &str = "book.bin";
+    let mut out_file: Option<&str> = None;
+    let repair = argv[1] == "repair-book";
+
+    while i < argc {
+        if i == 0 {
+            //pass
+        }
+        else if i == 1 {
+            // skip: check-book or repair-book
+        }
+        else if argv[i] == "-bin" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("check_book() -bin : missing argument\n");
+            }
+            bin_file = argv[i].as_str();
+        }
+        else if argv[i] == "-out" && repair {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("repair_book() -out : missing argument\n");
+            }
+            out_file = Some(argv[i].as_str());
+        }
+        else {
+            let tmp = format!("check_book(): unknown option \"{}\"\n",argv[i]);
+            util::my_fatal(tmp.as_str());
+        }
+        i += 1;
+    }
+
+    if !repair {
+        book_check::check_book(bin_file);
+    }
+    else if let Some(out_file) = out_file {
+        book_check::repair_book(bin_file, out_file);
+    }
+    else {
+        util::my_fatal("repair_book(): missing -out\n");
+    }
+}
+
+
+pub fn check_args_prune_book(argc: usize, argv: Vec<String>) {
+
+    let mut i: usize = 0;
+    let mut bin_file: &str = "book.bin";
+    let mut out_file: Option<&str> = None;
+    let mut options = book_prune::SpruneOptions::new();
+
+    while i < argc {
+        if i == 0 {
+            //pass
+        }
+        else if argv[i] == "prune-book" {
+            // skip
+        }
+        else if argv[i] == "-bin" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("prune_book() -bin : missing argument\n");
+            }
+            bin_file = argv[i].as_str();
+        }
+        else if argv[i] == "-out" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("prune_book() -out : missing argument\n");
+            }
+            out_file = Some(argv[i].as_str());
+        }
+        else if argv[i] == "-roots" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("prune_book() -roots : missing argument\n");
+            }
+            match std::fs::read_to_string(argv[i].as_str()) {
+                Ok(text) => {
+                    if !options.add_roots(text.as_str()) {
+                        let tmp = format!("prune_book() -roots : invalid FEN in \"{}\"\n", argv[i]);
+                        util::my_fatal(tmp.as_str());
+                    }
+                },
+                Err(e) => {
+                    let tmp = format!("prune_book() -roots : error {} reading file {}\n", e, argv[i]);
+                    util::my_fatal(tmp.as_str());
+                },
+            }
+        }
+        else if argv[i] == "-max-ply" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("prune_book() -max-ply : missing argument\n");
+            }
+            options.max_ply = parse_positive("prune_book() -max-ply : numerical argument\n", &argv[i]) as usize;
+        }
+        else if argv[i] == "-min-share" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("prune_book() -min-share : missing argument\n");
+            }
+            match argv[i].parse::<f64>() {
+                Ok(share) if (0.0..=100.0).contains(&share) => options.min_share = share,
+                _ => util::my_fatal("prune_book() -min-share : percentage between 0 and 100\n"),
+            }
+        }
+        else {
+            let tmp = format!("prune_book(): unknown option \"{}\"\n",argv[i]);
+            util::my_fatal(tmp.as_str());
+        }
+        i += 1;
+    }
+
+    match out_file {
+        Some(out_file) => book_prune::prune_book(bin_file, out_file, &options),
+        None => util::my_fatal("prune_book(): missing -out\n"),
+    }
+}
+
+
+pub fn check_args_edit_book(argc: usize, argv: Vec<String>) {
+
+    let mut i: usize = 0;
+    let mut bin_file: &str = "book.bin";
+    let mut out_file: Option<&str> = None;
+    let mut fen: &str = util::START_FEN;
+    let mut move_: Option<&str> = None;
+    let mut weight: Option<u16> = None;
+    let action: &str = if argc > 2 { argv[2].as_str() } else { "" };
+
+    if !["add", "remove", "set-weight", "forbid"].contains(&action) {
+        let tmp = format!("edit_book(): unknown command \"{}\", add, remove, set-weight or forbid\n", action);
+        util::my_fatal(tmp.as_str());
+    }
+
+    while i < argc {
+        if i <= 2 {
+            // skip: edit-book and the command
+        }
+        else if argv[i] == "-bin" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("edit_book() -bin : missing argument\n");
+            }
+            bin_file = argv[i].as_str();
+        }
+        else if argv[i] == "-out" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("edit_book() -out : missing argument\n");
+            }
+            out_file = Some(argv[i].as_str());
+        }
+        else if argv[i] == "-fen" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("edit_book() -fen : missing argument\n");
+            }
+            fen = argv[i].as_str();
+        }
+        else if argv[i] == "-move" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("edit_book() -move : missing argument\n");
+            }
+            move_ = Some(argv[i].as_str());
+        }
+        else if argv[i] == "-weight" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("edit_book() -weight : missing argument\n");
+            }
+            match argv[i].parse::<u16>() {
+                Ok(w) => weight = Some(w),
+                Err(_) => util::my_fatal("edit_book() -weight : number between 0 and 65535\n"),
+            }
+        }
+        else {
+            let tmp = format!("edit_book(): unknown option \"{}\"\n",argv[i]);
+            util::my_fatal(tmp.as_str());
+        }
+        i += 1;
+    }
+
+    let move_ = move_.unwrap_or_else(|| {
+        util::my_fatal("edit_book(): missing -move\n");
+        ""
+    });
+    let mut book = match book_edit::SeditableBook::load(bin_file) {
+        Ok(book) => book,
+        Err(e) => {
+            let tmp = format!("edit_book(): error {} reading book file {}\n", e, bin_file);
+            util::my_fatal(tmp.as_str());
+            return;
+        },
+    };
+
+    let result = match action {
+        "add" => book.add_move(fen, move_, weight.unwrap_or(1)).map(|_| 1),
+        "remove" => book.remove_move(fen, move_).map(|_| 1),
+        "set-weight" => match weight {
+            Some(weight) => book.set_weight(fen, move_, weight).map(|_| 1),
+            None => {
+                util::my_fatal("edit_book() set-weight : missing -weight\n");
+                return;
+            },
+        },
+        _ => book.forbid_move(fen, move_),
+    };
+    match result {
+        Ok(changed) => println!("changed entries: {}", changed),
+        Err(e) => {
+            let tmp = format!("edit_book() {} \"{}\": {}\n", action, move_, e);
+            util::my_fatal(tmp.as_str());
+        },
+    }
+
+    let out_file = out_file.unwrap_or(bin_file);
+    if let Err(e) = book.save(out_file) {
+        let tmp = format!("edit_book(): error {} creating book file {}\n", e, out_file);
+        util::my_fatal(tmp.as_str());
+    }
+}
+
+
+pub fn check_args_perft(argc: usize, argv: Vec<String>) {
+
+    let mut i: usize = 0;
+    let mut fen: &str;
+    let mut depth: i32 = 4;
+    let mut divide: bool = false;
+    let mut threads: usize = 1;
+    let mut hash_mb: usize = 0;
+
+    fen = util::START_FEN;
+
+    while i < argc {
+        if i == 0 {
+            //pass
+        }
+        else if argv[i] == "perft" {
+            // skip
+        }
+        else if argv[i] == "-fen" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("perft() -fen : missing argument\n");
+            }
+            fen = argv[i].as_str();
+        }
+        else if argv[i] == "-depth" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("perft() -depth : missing argument\n");
+            }
+            depth = parse_positive("perft() -depth : numerical argument\n", &argv[i]);
+        }
+        else if argv[i] == "-divide" {
+            divide = true;
+        }
+        else if argv[i] == "-threads" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("perft() -threads : missing argument\n");
+            }
+            threads = parse_positive("perft() -threads : numerical argument\n", &argv[i]) as usize;
+        }
+        else if argv[i] == "-hash" {
+            i += 1;
+            if i >= argc {
+                util::my_fatal("perft() -hash : missing argument\n");
+            }
+            let res = argv[i].parse::<usize>();
+            if res.is_err() {
+                util::my_fatal("perft() -hash : numerical argument\n");
+            }
+            hash_mb = res.unwrap_or(0);
+        }
+        else {
+            let tmp = format!("perft(): unknown option \"{}\"\n",argv[i]);
+            util::my_fatal(tmp.as_str());
+        }
+        i += 1;
+    }
+
+    if divide && (threads > 1 || hash_mb > 0) {
+        util::my_fatal("perft() -divide : it can't be used with -threads or -hash\n");
     }
 
-    if args.len() == 2 && (
-            &args[1] == "help"   || 
-            &args[1] == "-help"  || 
-            &args[1] == "--help" ||  
-            &args[1] == "-h"     ||  
-            &args[1] == "/?" ) {
-        println!("{}",HELP_MESSAGE);
-        std::process::exit(util::EXIT_SUCCESS);
+    let mut board: board::Sboard = board::Sboard::new();
+    if !board.set_fen(fen) {
+        let tmp = format!("perft(): invalid FEN \"{}\"\n", fen);
+        util::my_fatal(tmp.as_str());
     }
 
-    if args.len() >= 2 && args[1] =="make-book" {
-        check_args(args.len(), args);
+    if divide {
+        perft::Perft::divide(&mut board, depth);
+    }
+    else {
+        perft::Perft::perft_threads(&mut board, depth, threads, hash_mb);
     }
 }
 
 
-pub fn check_args(argc: usize, argv: Vec<String>) {
+pub fn check_args_perft_suite(argc: usize, argv: Vec<String>) {
 
     let mut i: usize = 0;
-    let mut pgn_file: &str;
-    let mut bin_file: &str;
-    let mut half_moves: i16 = 20;
+    let mut epd_file: &str;
+    let mut depth: i32 = 6;
 
-    pgn_file = "book.pgn";
-    bin_file = "book.bin";
+    epd_file = "";
 
     while i < argc {
         if i == 0 {
             //pass
         }
-        else if argv[i] == "make-book"{
+        else if argv[i] == "perft-suite" {
             // skip
         }
-        else if argv[i] == "-pgn" {
-            i += 1;
-            if i >= argc {
-                util::my_fatal("book_make() -pgn : missing argument\n");
-            }
-            pgn_file = argv[i].as_str();
-        }
-        else if argv[i] == "-bin" {
+        else if argv[i] == "-epd" {
             i += 1;
             if i >= argc {
-                util::my_fatal("book_make() -bin : missing argument\n");
+                util::my_fatal("perft_suite() -epd : missing argument\n");
             }
-            bin_file =argv[i].as_str();
+            epd_file = argv[i].as_str();
         }
-        else if argv[i] == "-max-ply" {
+        else if argv[i] == "-depth" {
             i += 1;
             if i >= argc {
-                util::my_fatal("book_make() -max-ply : missing argument\n");
+                util::my_fatal("perft_suite() -depth : missing argument\n");
             }
-            let res = argv[i].parse::<i16>();
-            if res.is_ok() {
-                half_moves = res.unwrap();
-            }
-            else {
-                util::my_fatal("book_make() -max-ply : numerical argument\n");
-            }
-            assert!(half_moves >= 0);
+            depth = parse_positive("perft_suite() -depth : numerical argument\n", &argv[i]);
         }
         else {
-            let tmp = format!("book_make(): unknown option \"{}\"\n",argv[i]);
+            let tmp = format!("perft_suite(): unknown option \"{}\"\n",argv[i]);
             util::my_fatal(tmp.as_str());
         }
         i += 1;
     }
 
-    make_book::make_new_book(pgn_file, bin_file, half_moves);
+    if epd_file.is_empty() {
+        util::my_fatal("perft_suite() : -epd is mandatory\n");
+    }
+
+    let (passed, total) = perft::Perft::perft_suite(epd_file, depth);
+    if passed < total {
+        std::process::exit(util::EXIT_FAILURE);
+    }
+}
+
+
+// a positive number, for -depth and alike
+
+fn parse_positive(error: &str, arg: &str) -> i32 {
+    let res = arg.parse::<i32>();
+    match res {
+        Ok(depth) if depth >= 1 => depth,
+        _ => {
+            util::my_fatal(error);
+            0
+        },
+    }
 }
 
 
@@ -188,4 +1261,971 @@ mod tests {
             None => {panic!("invalid move");},
         };
     }
+
+    fn play_moves(board: &mut board::Sboard, game: &[&str]) {
+        for jug in game.iter() {
+            let mut mov = san::move_from_san(jug.to_string(), board).unwrap();
+            assert!(board.make_move(&mut mov).is_some());
+        }
+    }
+
+    #[test]
+    fn test_game_status() {
+        // fool's mate
+        let mut board: board::Sboard = board::Sboard::new();
+        play_moves(&mut board, &["f3", "e5", "g4", "Qh4#"]);
+        assert_eq!(board.status(), board::GameStatus::Checkmate);
+
+        // stalemate
+        assert!(board.set_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"));
+        assert_eq!(board.status(), board::GameStatus::Stalemate);
+
+        // same coloured bishops
+        assert!(board.set_fen("4k3/8/3b4/8/8/2B5/8/4K3 w - - 0 1"));
+        assert_eq!(board.status(), board::GameStatus::InsufficientMaterial);
+        assert!(board.set_fen("4k3/8/2b5/8/8/2B5/8/4K3 w - - 0 1"));
+        assert_eq!(board.status(), board::GameStatus::Ongoing);
+
+        assert!(board.set_fen("4k3/8/8/8/8/8/8/R3K3 w - - 100 80"));
+        assert_eq!(board.status(), board::GameStatus::FiftyMoves);
+
+        // the en passant right of 2...d5 is gone when the position repeats
+        board = board::Sboard::new();
+        play_moves(&mut board, &["e4", "c6", "e5", "d5", "Nf3", "Nd7", "Ng1", "Nb8",
+            "Nf3", "Nd7", "Ng1", "Nb8"]);
+        assert_eq!(board.repetitions(), 2);
+        assert_eq!(board.status(), board::GameStatus::Ongoing);
+        play_moves(&mut board, &["Nf3", "Nd7", "Ng1", "Nb8"]);
+        assert_eq!(board.status(), board::GameStatus::ThreefoldRepetition);
+    }
+
+    #[test]
+    fn test_perft_divide() {
+        // "kiwipete", with castles, en passant and promotions
+        let mut board: board::Sboard = board::Sboard::new();
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert!(board.set_fen(fen));
+
+        assert_eq!(perft::Perft::divide(&mut board, 1), 48);
+        assert_eq!(perft::Perft::divide(&mut board, 3), 97862);
+        assert_eq!(board.get_fen(), fen);
+
+        assert!(board.set_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"));
+        assert_eq!(perft::Perft::mini_max(&mut board, 4), 43238);
+    }
+
+    #[test]
+    fn test_perft_parallel() {
+        let mut board: board::Sboard = board::Sboard::new();
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert!(board.set_fen(fen));
+
+        // four threads without hash, then two threads with a small table
+        let mut tables: Vec<perft::SperftHash> = Vec::new();
+        for _ in 0..4 {
+            tables.push(perft::SperftHash::new(0));
+        }
+        assert_eq!(perft::Perft::count_parallel(&mut board, 3, &mut tables), 97862);
+
+        let mut tables: Vec<perft::SperftHash> = Vec::new();
+        for _ in 0..2 {
+            tables.push(perft::SperftHash::new(1024));
+        }
+        assert_eq!(perft::Perft::count_parallel(&mut board, 3, &mut tables), 97862);
+        assert_eq!(perft::Perft::count_parallel(&mut board, 3, &mut tables), 97862);
+        assert_eq!(tables.len(), 2);
+        assert_eq!(board.get_fen(), fen);
+    }
+
+    #[test]
+    fn test_resolve_san() {
+        let mut board: board::Sboard = board::Sboard::new();
+
+        // the knight of c3 is pinned, so Ne2 can only be the one of g1
+        assert!(board.set_fen("4k3/8/8/b7/8/2N5/8/4K1N1 w - - 0 1"));
+        let mov = san::resolve_san("Ne2", &mut board).unwrap();
+        assert_eq!(mov.to_uci(), "g1e2");
+
+        // both rooks can go to d1
+        assert!(board.set_fen("4k3/8/8/8/8/8/4K3/R6R w - - 0 1"));
+        assert_eq!(san::resolve_san("Rd1", &mut board).err(),
+            Some(san::SanError::Ambiguous(vec!["a1d1".to_string(), "h1d1".to_string()])));
+        assert_eq!(san::resolve_san("Rhd1", &mut board).unwrap().to_uci(), "h1d1");
+        assert_eq!(san::resolve_san("Nf3", &mut board).err(), Some(san::SanError::NoSuchMove));
+        assert_eq!(san::resolve_san("O-O", &mut board).err(), Some(san::SanError::NoSuchMove));
+        assert_eq!(san::resolve_san("Xz9", &mut board).err(), Some(san::SanError::Malformed));
+
+        // promotion with and without '='
+        assert!(board.set_fen("8/P3k3/8/8/8/8/8/4K3 w - - 0 1"));
+        assert_eq!(san::resolve_san("a8=N", &mut board).unwrap().to_uci(), "a7a8n");
+        assert_eq!(san::resolve_san("a8Q+", &mut board).unwrap().to_uci(), "a7a8q");
+        assert_eq!(san::resolve_san("a8", &mut board).err(), Some(san::SanError::NoSuchMove));
+
+        // the king can't take its rook away from e1/e8
+        assert!(board.set_fen("rk6/8/8/8/8/8/8/K7 b - - 0 1"));
+        assert_eq!(san::resolve_san("b8a8", &mut board).err(), Some(san::SanError::NoSuchMove));
+    }
+
+    #[test]
+    fn test_san_dialects() {
+        let mut lenient = san::SsanParser::new();
+        let mut strict = san::SsanParser::strict();
+        let mut board: board::Sboard = board::Sboard::new();
+
+        assert_eq!(lenient.parse("Ng1-f3", &mut board).unwrap().to_uci(), "g1f3");
+        assert_eq!(lenient.parse("e2e4", &mut board).unwrap().to_uci(), "e2e4");
+        assert_eq!(lenient.parse("Nf3!?", &mut board).unwrap().to_uci(), "g1f3");
+        assert_eq!(strict.parse("Ng1-f3", &mut board).err(), Some(san::SanError::Malformed));
+        assert_eq!(strict.parse("e2e4", &mut board).err(), Some(san::SanError::Malformed));
+
+        // castles with zeros, or the king takes its rook
+        assert!(board.set_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1"));
+        assert_eq!(lenient.parse("0-0", &mut board).unwrap().to_uci(), "e1g1");
+        assert_eq!(lenient.parse("e1h1", &mut board).unwrap().to_uci(), "e1g1");
+        assert_eq!(strict.parse("0-0", &mut board).err(), Some(san::SanError::Malformed));
+        assert_eq!(strict.parse("O-O+", &mut board).unwrap().to_uci(), "e1g1");
+
+        // en passant suffix and promotion without '='
+        assert!(board.set_fen("4k3/P7/8/3pP3/8/8/8/4K3 w - d6 0 1"));
+        assert_eq!(lenient.parse("exd6e.p.", &mut board).unwrap().to_uci(), "e5d6");
+        assert_eq!(lenient.parse("exd6ep", &mut board).unwrap().to_uci(), "e5d6");
+        assert_eq!(lenient.parse("a8Q", &mut board).unwrap().to_uci(), "a7a8q");
+        assert_eq!(strict.parse("a8Q", &mut board).err(), Some(san::SanError::Malformed));
+
+        // null moves only give the turn
+        let hash = board.hash_key;
+        for null in ["--", "Z0"].iter() {
+            let mut mov = lenient.parse(null, &mut board).unwrap();
+            assert_eq!(mov.to_uci(), "0000");
+            assert!(board.make_move(&mut mov).is_some());
+            assert_eq!(board.side, color::BLACK);
+            let hash_null = board.hash_key;
+            assert_eq!(hash_null, zobrist::hash_key(&mut board));
+            board.undo_move(&mut mov);
+            assert_eq!(board.side, color::WHITE);
+            assert_eq!(board.hash_key, hash);
+            assert_eq!(lenient.parse("exd6", &mut board).unwrap().to_uci(), "e5d6");
+        }
+        assert_eq!(strict.parse("--", &mut board).err(), Some(san::SanError::Malformed));
+    }
+
+    #[test]
+    fn test_piece_letters() {
+        let mut board: board::Sboard = board::Sboard::new();
+        let mut parser = san::SsanParser::new();
+
+        // spanish, german and figurines
+        assert!(parser.set_pieces("es"));
+        assert_eq!(parser.parse("Cf3", &mut board).unwrap().to_uci(), "g1f3");
+        assert!(parser.set_pieces("de"));
+        assert_eq!(parser.parse("Sf3", &mut board).unwrap().to_uci(), "g1f3");
+        assert_eq!(parser.parse("♘f3", &mut board).unwrap().to_uci(), "g1f3");
+        assert_eq!(parser.parse("Nf3", &mut board).err(), Some(san::SanError::Malformed));
+        assert!(!parser.set_pieces("xx"));
+
+        // auto: R is a rook until the moves are spanish, then it is the king
+        assert!(board.set_fen("4k3/8/8/8/8/2N5/8/R3K3 w - - 0 1"));
+        assert!(parser.set_pieces("auto"));
+        assert_eq!(parser.parse("Rd1", &mut board).unwrap().to_uci(), "a1d1");
+        assert_eq!(parser.parse("Cd5", &mut board).unwrap().to_uci(), "c3d5");
+        assert_eq!(parser.letters.name, "es");
+        assert_eq!(parser.parse("Rd1", &mut board).unwrap().to_uci(), "e1d1");
+
+        // the SAN of the moves
+        let en = san::piece_letters("en").unwrap();
+        let es = san::piece_letters("es").unwrap();
+        let figurine = san::piece_letters("figurine").unwrap();
+        assert!(board.set_fen("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1"));
+        let tests = [("a1a8", "Rxa8+", "Txa8+"), ("e1c1", "O-O-O", "O-O-O"), ("a1d1", "Rd1", "Td1"),
+            ("b7b8q", "b8=Q+", "b8=D+"), ("b7a8n", "bxa8=N", "bxa8=C"), ("e1e2", "Ke2", "Re2")];
+        for (uci, san_en, san_es) in tests.iter() {
+            let mov = san::resolve_san(uci, &mut board).unwrap();
+            assert_eq!(san::move_to_san(&mov, &mut board, en), *san_en);
+            assert_eq!(san::move_to_san(&mov, &mut board, es), *san_es);
+        }
+        let mov = san::resolve_san("Ra2", &mut board).unwrap();
+        assert_eq!(san::move_to_san(&mov, &mut board, figurine), "♖a2");
+
+        // the file, or the rank if the file is the same
+        assert!(board.set_fen("4k3/8/8/R7/8/8/4K3/R6R w - - 0 1"));
+        let mov = san::resolve_san("h1d1", &mut board).unwrap();
+        assert_eq!(san::move_to_san(&mov, &mut board, en), "Rhd1");
+        let mov = san::resolve_san("a1a3", &mut board).unwrap();
+        assert_eq!(san::move_to_san(&mov, &mut board, en), "R1a3");
+
+        // mate
+        assert!(board.set_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"));
+        let mov = san::resolve_san("Ra8", &mut board).unwrap();
+        assert_eq!(san::move_to_san(&mov, &mut board, en), "Ra8#");
+    }
+
+    #[test]
+    fn test_pgn_figurines() {
+        let pgn_file = std::env::temp_dir().join("rschessbook_figurines.pgn");
+        std::fs::write(&pgn_file, 
+            "[White \"Muñoz, José\"]\n[Result \"*\"]\n\n1. e4 e5 2. ♘f3 ♞c6 3. Ab5 *\n").unwrap();
+
+        let mut pgn: pgn::Spgn = pgn::Spgn::new();
+        let mut board: board::Sboard = board::Sboard::new();
+        let mut parser = san::SsanParser::new();
+        let mut san: String = String::new();
+        assert!(parser.set_pieces("auto"));
+
+        pgn.pgn_open(pgn_file.to_str().unwrap());
+        assert!(pgn.pgn_next_game());
+        let mut moves: Vec<String> = Vec::new();
+        let mut column: i32 = 0;
+        while pgn.pgn_next_move(&mut san, 256) {
+            let mut mov = parser.parse(san.as_str(), &mut board).unwrap();
+            moves.push(mov.to_uci());
+            board.make_move(&mut mov);
+            column = pgn.move_column;
+        }
+        assert_eq!(moves, ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5"]);
+        assert_eq!(column, 23);
+        std::fs::remove_file(&pgn_file).unwrap();
+    }
+
+    #[test]
+    fn test_pgn_encoding() {
+        assert_eq!(pgn::decode_byte(0x93, pgn::Encoding::Windows1252), 0x201C);
+        assert_eq!(pgn::decode_byte(0x93, pgn::Encoding::Latin1), 0x93);
+        assert_eq!(pgn::decode_byte(0xE1, pgn::Encoding::Windows1252), 0xE1);
+        let mut encoding = pgn::SpgnEncoding::new();
+        assert!(!encoding.set_fallback("utf-8"));
+        assert!(encoding.set_encoding("Latin-1"));
+
+        // with BOM, a latin-1 comment and another in utf-8
+        let pgn_file = std::env::temp_dir().join("rschessbook_encoding.pgn");
+        let text: &[u8] = b"[Result \"*\"]\n\n1. e4 {Gonz\xe1lez} e5 {Mu\xc3\xb1oz \x93x\x94} Nf3 *\n";
+        let with_bom: Vec<u8> = [&[0xEF, 0xBB, 0xBF][..], text].concat();
+
+        // the column of Nf3 in characters, or in bytes when the utf-8 is read as latin-1
+        let tests = [(&with_bom, pgn::SpgnEncoding::new(), 32), (&with_bom, encoding, 32), 
+            (&text.to_vec(), pgn::SpgnEncoding::new(), 32), (&text.to_vec(), encoding, 33)];
+        for (bytes, encoding, nf3_column) in tests.iter() {
+            std::fs::write(&pgn_file, bytes).unwrap();
+
+            let mut pgn: pgn::Spgn = pgn::Spgn::new();
+            let mut san: String = String::new();
+            pgn.encoding = *encoding;
+            pgn.pgn_open(pgn_file.to_str().unwrap());
+            assert!(pgn.pgn_next_game());
+            assert_eq!(pgn.get_result(), "*");
+            let mut columns: Vec<i32> = Vec::new();
+            while pgn.pgn_next_move(&mut san, 256) {
+                assert_eq!(pgn.move_line, 3);
+                columns.push(pgn.move_column);
+            }
+            assert_eq!(columns, [3, 17, *nf3_column]);
+        }
+        std::fs::remove_file(&pgn_file).unwrap();
+    }
+
+    #[test]
+    fn test_game_filter() {
+        assert_eq!(filter::time_control("180+2"), Some(filter::TimeControl::Blitz));
+        assert_eq!(filter::time_control("60"), Some(filter::TimeControl::Bullet));
+        assert_eq!(filter::time_control("40/7200:3600"), Some(filter::TimeControl::Classical));
+        assert_eq!(filter::time_control("1/259200"), Some(filter::TimeControl::Correspondence));
+        assert_eq!(filter::time_control("-"), None);
+        assert_eq!(filter::parse_date("2019.??.??", 0), Some(20190000));
+        assert_eq!(filter::parse_date("2019.05", 99), Some(20190599));
+        assert_eq!(filter::parse_date("????.??.??", 0), None);
+
+        let games = [
+            ("Tata Steel", "2019.01.20", "2780", "2750", "5400+30", "Standard", "1-0"),
+            ("Titled Arena", "2021.03.04", "2900", "2100", "60+0", "", "0-1"),
+            ("Club ch", "2005.??.??", "1900", "?", "", "", "*"),
+            ("Freestyle", "2024.02.09", "2800", "2800", "900+10", "Chess960", "1/2-1/2"),
+        ];
+        let mut text = String::new();
+        for (event, date, white, black, time, variant, result) in games.iter() {
+            text.push_str(&format!(
+                "[Event \"{}\"]\n[Date \"{}\"]\n[WhiteElo \"{}\"]\n[BlackElo \"{}\"]\n", 
+                event, date, white, black));
+            if !time.is_empty() { text.push_str(&format!("[TimeControl \"{}\"]\n", time)); }
+            if !variant.is_empty() { text.push_str(&format!("[Variant \"{}\"]\n", variant)); }
+            text.push_str(&format!("[Result \"{}\"]\n\n1. e4 e5 {}\n\n", result, result));
+        }
+        let pgn_file = std::env::temp_dir().join("rschessbook_filter.pgn");
+        std::fs::write(&pgn_file, text).unwrap();
+
+        // the games of the file that pass the filter
+        let accepted = |game_filter: &filter::SgameFilter| -> Vec<i32> {
+            let mut pgn: pgn::Spgn = pgn::Spgn::new();
+            let mut san: String = String::new();
+            let mut found: Vec<i32> = Vec::new();
+            pgn.pgn_open(pgn_file.to_str().unwrap());
+            let mut game = 0;
+            while pgn.pgn_next_game() {
+                if game_filter.accepts(&pgn) {
+                    found.push(game);
+                }
+                while pgn.pgn_next_move(&mut san, 256) {}
+                game += 1;
+            }
+            found
+        };
+
+        let mut game_filter = filter::SgameFilter::new();
+        assert_eq!(accepted(&game_filter), [0, 1, 2]);
+        game_filter.only_standard = false;
+        assert_eq!(accepted(&game_filter), [0, 1, 2, 3]);
+        game_filter.only_finished = true;
+        assert_eq!(accepted(&game_filter), [0, 1, 3]);
+
+        let mut game_filter = filter::SgameFilter::new();
+        game_filter.min_elo = Some(2800);
+        assert_eq!(accepted(&game_filter), [1]);
+        game_filter.min_elo = None;
+        game_filter.min_elo_both = Some(2700);
+        assert_eq!(accepted(&game_filter), [0]);
+
+        let mut game_filter = filter::SgameFilter::new();
+        assert!(game_filter.set_date_from("2005"));
+        assert!(game_filter.set_date_to("2019.06"));
+        assert_eq!(accepted(&game_filter), [0, 2]);
+        assert!(!game_filter.set_date_to("June"));
+
+        let mut game_filter = filter::SgameFilter::new();
+        game_filter.event = Some(String::from("arena"));
+        assert_eq!(accepted(&game_filter), [1]);
+        game_filter.event = None;
+        assert!(game_filter.set_time_controls("bullet,classical"));
+        assert_eq!(accepted(&game_filter), [0, 1]);
+        assert!(!game_filter.set_time_controls("lightning"));
+
+        std::fs::remove_file(&pgn_file).unwrap();
+    }
+
+    // make-book of a pgn text with the options, the entries and the metadata of the book
+    fn make_test_book(name: &str, text: &str, options: &mut make_book::SmakeOptions)
+            -> (Vec<book::SfinalEntry>, Vec<(String, String)>) {
+        let pgn_file = std::env::temp_dir().join(format!("{}.pgn", name));
+        let bin_file = std::env::temp_dir().join(format!("{}.bin", name));
+        std::fs::write(&pgn_file, text).unwrap();
+        assert!(options.add_pgn(pgn_file.to_str().unwrap()));
+        options.bin_file = bin_file.to_str().unwrap().to_string();
+        make_book::make_new_book(options);
+        let entries = book::read_book_file(&options.bin_file).unwrap();
+        let metadata = make_book::read_metadata(&options.bin_file);
+        std::fs::remove_file(&pgn_file).unwrap();
+        std::fs::remove_file(&bin_file).unwrap();
+        std::fs::remove_file(make_book::metadata_file(&options.bin_file)).unwrap();
+        (entries, metadata)
+    }
+
+    // the moves in uci and the weights of the entries, sorted
+    fn uci_weights(entries: &[book::SfinalEntry]) -> Vec<(String, u16)> {
+        let mut found: Vec<(String, u16)> = entries.iter()
+            .map(|e| (moves::get_uci_format(e.move_).trim().to_string(), e.weight)).collect();
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn test_player_book() {
+        assert!(filter::same_player("Carlsen, Magnus", "Magnus Carlsen"));
+        assert!(filter::same_player("Magnus Carlsen", "Carlsen,M."));
+        assert!(filter::same_player("carlsen", "Carlsen, Magnus"));
+        assert!(!filter::same_player("Carlsen, Magnus", "Carlsen, Henrik"));
+        assert!(!filter::same_player("Carlsen", ""));
+
+        let text = concat!(
+            "[White \"Carlsen, Magnus\"]\n[Black \"Nakamura, Hikaru\"]\n[Result \"1-0\"]\n\n1. e4 c5 2. Nf3 1-0\n\n",
+            "[White \"Caruana, Fabiano\"]\n[Black \"Magnus Carlsen\"]\n[Result \"0-1\"]\n\n1. d4 Nf6 2. c4 0-1\n\n",
+            "[White \"Anand, Viswanathan\"]\n[Black \"Kramnik, Vladimir\"]\n[Result \"1/2-1/2\"]\n\n1. c4 1/2-1/2\n");
+
+        // the moves of the book without the keys
+        let book_moves = |player: Option<usize>, opponent_moves: bool| -> Vec<String> {
+            let mut options = make_book::SmakeOptions::new();
+            options.filter.player = Some(String::from("Magnus Carlsen"));
+            options.filter.player_color = player;
+            options.opponent_moves = opponent_moves;
+            options.keep_zero = true;
+            uci_weights(&make_test_book("rschessbook_player", text, &mut options).0).into_iter().map(|e| e.0).collect()
+        };
+
+        assert_eq!(book_moves(None, false), ["e2e4", "g1f3", "g8f6"]);
+        assert_eq!(book_moves(Some(color::WHITE), false), ["e2e4", "g1f3"]);
+        assert_eq!(book_moves(Some(color::BLACK), true), ["c2c4", "d2d4", "g8f6"]);
+    }
+
+    #[test]
+    fn test_weighted_games() {
+        let text = concat!(
+            "[WhiteElo \"2400\"]\n[Date \"2024.01.01\"]\n[Result \"1-0\"]\n\n1. e4 e5 1-0\n\n",
+            "[BlackElo \"2200\"]\n[Date \"2024.01.01\"]\n[Result \"0-1\"]\n\n1. e4 c5 0-1\n\n",
+            "[Date \"2004.01.01\"]\n[Result \"1-0\"]\n\n1. d4 d5 1-0\n\n",
+            "[Result \"1/2-1/2\"]\n\n1. d4 d5 1/2-1/2\n");
+
+        // 2 points for a win and 1 for a draw of the side that moves
+        let mut options = make_book::SmakeOptions::new();
+        options.keep_zero = true;
+        assert_eq!(uci_weights(&make_test_book("rschessbook_weights", text, &mut options).0), [
+            ("c7c5".to_string(), 2), ("d2d4".to_string(), 3), ("d7d5".to_string(), 1),
+            ("e2e4".to_string(), 2), ("e7e5".to_string(), 0)]);
+
+        // the lost e5 is not stored without -keep-zero
+        let mut options = make_book::SmakeOptions::new();
+        assert_eq!(uci_weights(&make_test_book("rschessbook_weights", text, &mut options).0), [
+            ("c7c5".to_string(), 2), ("d2d4".to_string(), 3), ("d7d5".to_string(), 1),
+            ("e2e4".to_string(), 2)]);
+
+        // the win of the 2400 counts 4 times, the one of the 2200 twice
+        let mut options = make_book::SmakeOptions::new();
+        options.game_weight.elo_threshold = Some(2000);
+        assert_eq!(uci_weights(&make_test_book("rschessbook_weights", text, &mut options).0), [
+            ("c7c5".to_string(), 4), ("d2d4".to_string(), 3), ("d7d5".to_string(), 1),
+            ("e2e4".to_string(), 8)]);
+
+        // the game of 2004 counts the half, as the one without date
+        let mut options = make_book::SmakeOptions::new();
+        options.game_weight.date_reference = Some(20240101);
+        options.game_weight.half_life = 20.0;
+        assert_eq!(uci_weights(&make_test_book("rschessbook_weights", text, &mut options).0), [
+            ("c7c5".to_string(), 2), ("d2d4".to_string(), 2), ("d7d5".to_string(), 1),
+            ("e2e4".to_string(), 2)]);
+
+        // the formulas, for c7c5 d2d4 d7d5 e2e4 e7e5
+        let formulas: [(&str, [u16; 5]); 6] = [
+            ("frequency", [1, 2, 2, 2, 1]), ("score", [5000, 3750, 2500, 2500, 0]), ("wins", [1, 1, 0, 1, 0]),
+            ("3-1-0", [3, 4, 1, 3, 0]), ("wilson", [207, 198, 27, 95, 0]), ("uniform", [1, 1, 1, 1, 1])];
+        for (name, weights) in formulas.iter() {
+            let mut options = make_book::SmakeOptions::new();
+            options.formula = book::WeightFormula::from_name(name).unwrap();
+            options.keep_zero = true;
+            let found: Vec<u16> = uci_weights(&make_test_book("rschessbook_weights", text, &mut options).0)
+                .into_iter().map(|e| e.1).collect();
+            assert_eq!(found, weights, "{}", name);
+        }
+        assert_eq!(book::WeightFormula::from_name("elo"), None);
+    }
+
+    #[test]
+    fn test_book_metadata() {
+        let mut options = make_book::SmakeOptions::new();
+        options.formula = book::WeightFormula::Wilson;
+        options.game_weight.elo_threshold = Some(2200);
+        let (_, metadata) = make_test_book("rschessbook_meta", "[Result \"1-0\"]\n\n1. e4 e5 1-0\n", &mut options);
+
+        let value = |key: &str| metadata.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+        assert_eq!(value("weight"), Some("wilson"));
+        assert_eq!(value("elo-weight"), Some("2200:200"));
+        assert_eq!(value("games"), Some("1"));
+        assert_eq!(value("entries"), Some("1"));     // e5 lost, of weight 0
+        assert_eq!(value("date-weight"), None);
+    }
+
+    #[test]
+    fn test_pgn_multipliers() {
+        let mut options = make_book::SmakeOptions::new();
+        assert!(options.add_pgn("correspondence.pgn:3"));
+        assert!(options.add_pgn("blitz.pgn:0.5"));
+        assert!(options.add_pgn("c:\\games\\club.pgn"));
+        assert!(!options.add_pgn("bad.pgn:0"));
+        assert!(!options.add_pgn("bad.pgn:2.5.1"));
+        assert!(options.add_pgn("games:2024.pgn"));
+        assert!(options.add_pgn("games.pgn:2x"));
+        assert_eq!(options.pgn_files, [("correspondence.pgn".to_string(), 3.0),
+            ("blitz.pgn".to_string(), 0.5), ("c:\\games\\club.pgn".to_string(), 1.0),
+            ("games:2024.pgn".to_string(), 1.0), ("games.pgn:2x".to_string(), 1.0)]);
+
+        // the wins of e4 count 3 times, the ones of d4 the half, the one of c4 once
+        let e4_file = std::env::temp_dir().join("rschessbook_e4.pgn");
+        let d4_file = std::env::temp_dir().join("rschessbook_d4.pgn");
+        std::fs::write(&e4_file, "[Result \"1-0\"]\n\n1. e4 1-0\n").unwrap();
+        std::fs::write(&d4_file, "[Result \"1-0\"]\n\n1. d4 1-0\n\n[Result \"1-0\"]\n\n1. d4 1-0\n").unwrap();
+
+        let mut options = make_book::SmakeOptions::new();
+        assert!(options.add_pgn(&format!("{}:3", e4_file.to_str().unwrap())));
+        assert!(options.add_pgn(&format!("{}:0.5", d4_file.to_str().unwrap())));
+        let (entries, metadata) = make_test_book("rschessbook_multipliers", 
+            "[Result \"1-0\"]\n\n1. c4 1-0\n", &mut options);
+        assert_eq!(uci_weights(&entries), [("c2c4".to_string(), 2), ("d2d4".to_string(), 2), ("e2e4".to_string(), 6)]);
+        assert_eq!(metadata.iter().filter(|(k, _)| k == "pgn").count(), 3);
+
+        std::fs::remove_file(&e4_file).unwrap();
+        std::fs::remove_file(&d4_file).unwrap();
+    }
+
+    #[test]
+    fn test_ply_limits() {
+        let text = "[Result \"1/2-1/2\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 1/2-1/2\n";
+        let moves = |max_plies: [i16; 2]| -> Vec<String> {
+            let mut options = make_book::SmakeOptions::new();
+            options.max_plies = max_plies;
+            uci_weights(&make_test_book("rschessbook_plies", text, &mut options).0).into_iter().map(|e| e.0).collect()
+        };
+        assert_eq!(moves([20, 20]).len(), 6);
+        assert_eq!(moves([1, 1]), ["e2e4"]);
+        assert_eq!(moves([5, 2]), ["e2e4", "e7e5", "f1b5", "g1f3"]);
+        assert_eq!(moves([0, 4]), ["b8c6", "e7e5"]);
+
+        // the first game does not reach 2 games in the initial position
+        let text = concat!(
+            "[Result \"1/2-1/2\"]\n\n1. e4 e5 2. Nf3 1/2-1/2\n\n",
+            "[Result \"1/2-1/2\"]\n\n1. e4 e5 2. Bc4 Nf6 1/2-1/2\n\n",
+            "[Result \"1/2-1/2\"]\n\n1. e4 c5 1/2-1/2\n");
+        let mut options = make_book::SmakeOptions::new();
+        options.leaf_cutoff = Some(2);
+        assert_eq!(uci_weights(&make_test_book("rschessbook_plies", text, &mut options).0), [
+            ("c7c5".to_string(), 1), ("e2e4".to_string(), 2), ("e7e5".to_string(), 1), 
+            ("f1c4".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_min_games() {
+        // e4 e5 is in 3 games, Nf3 in 2, c5 and Bc4 only in one
+        let text = concat!(
+            "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 Nc6 1-0\n\n",
+            "[Result \"1/2-1/2\"]\n\n1. e4 e5 2. Nf3 Nf6 1/2-1/2\n\n",
+            "[Result \"0-1\"]\n\n1. e4 e5 2. Bc4 0-1\n\n",
+            "[Result \"0-1\"]\n\n1. e4 c5 0-1\n");
+        let mut options = make_book::SmakeOptions::new();
+        options.min_games = Some(2);
+        assert_eq!(uci_weights(&make_test_book("rschessbook_min_games", text, &mut options).0), [
+            ("e2e4".to_string(), 3), ("e7e5".to_string(), 3), ("g1f3".to_string(), 3)]);
+
+        // with the cutoff of one pass the first games are lost and the last ones are stored
+        let mut options = make_book::SmakeOptions::new();
+        options.leaf_cutoff = Some(2);
+        assert_eq!(uci_weights(&make_test_book("rschessbook_min_games", text, &mut options).0), [
+            ("c7c5".to_string(), 2), ("e2e4".to_string(), 1), ("e7e5".to_string(), 3), 
+            ("g1f3".to_string(), 1), ("g8f6".to_string(), 1)]);
+
+        // the cutoff counts the games of all the files
+        let first_file = std::env::temp_dir().join("rschessbook_leaf_cutoff.pgn");
+        std::fs::write(&first_file, "[Result \"1-0\"]\n\n1. e4 e5 1-0\n").unwrap();
+        let mut options = make_book::SmakeOptions::new();
+        assert!(options.add_pgn(first_file.to_str().unwrap()));
+        options.leaf_cutoff = Some(2);
+        let (entries, _) = make_test_book("rschessbook_min_games", "[Result \"1-0\"]\n\n1. e4 e5 1-0\n", &mut options);
+        assert_eq!(uci_weights(&entries), [("e2e4".to_string(), 2)]);
+        std::fs::remove_file(&first_file).unwrap();
+
+        // and a position repeated in a game once
+        let mut options = make_book::SmakeOptions::new();
+        options.leaf_cutoff = Some(3);
+        assert_eq!(uci_weights(&make_test_book("rschessbook_min_games", concat!(
+            "[Result \"1-0\"]\n\n1. Nf3 Nf6 2. Ng1 Ng8 3. e4 1-0\n\n",
+            "[Result \"1-0\"]\n\n1. d4 1-0\n"), &mut options).0), []);
+    }
+
+    #[test]
+    fn test_sorted_entries() {
+        // d4 has more weight than e4 in the initial position, c4 only draws
+        let text = concat!(
+            "[Result \"1-0\"]\n\n1. e4 1-0\n\n",
+            "[Result \"1-0\"]\n\n1. d4 1-0\n\n",
+            "[Result \"1-0\"]\n\n1. d4 1-0\n\n",
+            "[Result \"1/2-1/2\"]\n\n1. c4 1/2-1/2\n\n",
+            "[Result \"1/2-1/2\"]\n\n1. Nf3 1/2-1/2\n\n",
+            "[Result \"0-1\"]\n\n1. b3 0-1\n");
+
+        // in the order of the book file
+        let book_moves = |keep_zero: bool| -> Vec<(String, u16)> {
+            let mut options = make_book::SmakeOptions::new();
+            options.keep_zero = keep_zero;
+            make_test_book("rschessbook_sorted", text, &mut options).0.iter()
+                .map(|e| (moves::get_uci_format(e.move_).trim().to_string(), e.weight)).collect()
+        };
+        // c4 and Nf3 by their encoded moves (g1f3 < c2c4)
+        assert_eq!(book_moves(false), [("d2d4".to_string(), 4), ("e2e4".to_string(), 2),
+            ("g1f3".to_string(), 1), ("c2c4".to_string(), 1)]);
+        assert_eq!(book_moves(true).last(), Some(&("b2b3".to_string(), 0)));
+    }
+
+    #[test]
+    fn test_move_stats() {
+        let stats = book::SmoveStats { key: 1, move_: 2, wins: 3, draws: 2, losses: 1, elo: 0, year: 0 };
+        assert_eq!(book::unpack_learn(stats.learn()), (3, 2, 1));
+        let stats = book::SmoveStats { wins: 4092, draws: 2046, losses: 0, ..stats };
+        assert_eq!(book::unpack_learn(stats.learn()), (1023, 512, 0));
+
+        let stats_file = std::env::temp_dir().join("rschessbook_stats.stats");
+        let mut options = make_book::SmakeOptions::new();
+        options.stats_file = Some(stats_file.to_str().unwrap().to_string());
+        options.learn_wdl = true;
+        options.keep_zero = true;
+        let (entries, metadata) = make_test_book("rschessbook_stats", concat!(
+            "[Date \"2019.05.01\"]\n[WhiteElo \"2500\"]\n[Result \"1-0\"]\n\n1. e4 e5 1-0\n\n",
+            "[Date \"2023.??.??\"]\n[WhiteElo \"2300\"]\n[Result \"1/2-1/2\"]\n\n1. e4 c5 1/2-1/2\n\n",
+            "[Result \"0-1\"]\n\n1. e4 e5 0-1\n"), &mut options);
+
+        // the entries of the book and the statistics are in the same order
+        let stats = make_book::read_stats(options.stats_file.as_ref().unwrap());
+        assert_eq!(stats.len(), entries.len());
+        let found = |uci: &str| -> book::SmoveStats {
+            let index = entries.iter().position(|e| moves::get_uci_format(e.move_).trim() == uci).unwrap();
+            assert_eq!(book::unpack_learn(entries[index].learn), 
+                (stats[index].wins, stats[index].draws, stats[index].losses));
+            stats[index].clone()
+        };
+        let e4 = found("e2e4");
+        assert_eq!((e4.wins, e4.draws, e4.losses, e4.elo, e4.year), (1, 1, 1, 2400, 2023));
+        let e5 = found("e7e5");
+        assert_eq!((e5.wins, e5.draws, e5.losses, e5.elo, e5.year), (1, 0, 1, 0, 2019));
+        assert_eq!(metadata.iter().find(|(k, _)| k == "learn"), Some(&("learn".to_string(), "wdl".to_string())));
+
+        std::fs::remove_file(&stats_file).unwrap();
+    }
+
+    #[test]
+    fn test_book_update() {
+        let first = "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 1-0\n\n[Result \"0-1\"]\n\n1. d4 d5 0-1\n";
+        let second = "[Result \"1/2-1/2\"]\n\n1. e4 c5 1/2-1/2\n\n[Result \"1-0\"]\n\n1. e4 e5 1-0\n";
+        let first_file = std::env::temp_dir().join("rschessbook_update1.pgn");
+        let second_file = std::env::temp_dir().join("rschessbook_update2.pgn");
+        let update_file = std::env::temp_dir().join("rschessbook_update.db");
+        let bin_file = std::env::temp_dir().join("rschessbook_update.bin");
+        std::fs::write(&first_file, first).unwrap();
+        std::fs::write(&second_file, second).unwrap();
+        let _ = std::fs::remove_file(&update_file);
+
+        let make = |files: &[&std::path::Path], update: bool, formula: book::WeightFormula| -> (Vec<u8>, String) {
+            let mut options = make_book::SmakeOptions::new();
+            for file in files.iter() {
+                assert!(options.add_pgn(file.to_str().unwrap()));
+            }
+            options.bin_file = bin_file.to_str().unwrap().to_string();
+            if update {
+                options.update_file = Some(update_file.to_str().unwrap().to_string());
+            }
+            options.formula = formula;
+            make_book::make_new_book(&mut options);
+            let games = make_book::read_metadata(&options.bin_file).into_iter()
+                .find(|(k, _)| k == "games").unwrap().1;
+            (std::fs::read(&bin_file).unwrap(), games)
+        };
+
+        // the same book at once or in two runs
+        let polyglot = book::WeightFormula::Polyglot;
+        let at_once = make(&[&first_file, &second_file], false, polyglot);
+        make(&[&first_file], true, polyglot);
+        assert_eq!(make(&[&second_file], true, polyglot), at_once);
+        assert_eq!(at_once.1, "4");
+
+        // and again with another formula, without new games
+        let frequency = book::WeightFormula::Frequency;
+        assert_eq!(make(&[], true, frequency), make(&[&first_file, &second_file], false, frequency));
+
+        std::fs::remove_file(&first_file).unwrap();
+        std::fs::remove_file(&second_file).unwrap();
+        std::fs::remove_file(&update_file).unwrap();
+        std::fs::remove_file(&bin_file).unwrap();
+        std::fs::remove_file(make_book::metadata_file(bin_file.to_str().unwrap())).unwrap();
+    }
+
+    #[test]
+    fn test_dedup() {
+        // the second game is the first one from another source, the third one 
+        // has the same moves with other players
+        let text = concat!(
+            "[White \"Carlsen, Magnus\"]\n[Black \"Anand, V\"]\n[Date \"2014.11.09\"]\n",
+            "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bb5 1-0\n\n",
+            "[Event \"Sochi\"]\n[White \"carlsen magnus\"]\n[Black \"Anand V.\"]\n[Date \"2014.11.09\"]\n",
+            "[Result \"1-0\"]\n\n1.e4 e5 2.Nf3 Nc6 3.Bb5! 1-0\n\n",
+            "[White \"Kasparov, Garry\"]\n[Black \"Karpov, Anatoly\"]\n[Date \"1985.09.03\"]\n",
+            "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bb5 1-0\n");
+        let e4 = |dedup: Option<make_book::Dedup>| -> u16 {
+            let mut options = make_book::SmakeOptions::new();
+            options.dedup = dedup;
+            uci_weights(&make_test_book("rschessbook_dedup", text, &mut options).0).into_iter()
+                .find(|e| e.0 == "e2e4").unwrap().1
+        };
+        assert_eq!(e4(None), 6);
+        assert_eq!(e4(Some(make_book::Dedup::Moves)), 2);
+        assert_eq!(e4(Some(make_book::Dedup::MovesAndPlayers)), 4);
+
+        // and in the first pass of -min-games, as in the second one
+        let mut options = make_book::SmakeOptions::new();
+        options.dedup = Some(make_book::Dedup::Moves);
+        options.min_games = Some(2);
+        assert_eq!(uci_weights(&make_test_book("rschessbook_dedup", text, &mut options).0), []);
+
+        // the games of a previous -update are not counted again
+        let update_file = std::env::temp_dir().join("rschessbook_dedup.db");
+        let update = || -> u16 {
+            let mut options = make_book::SmakeOptions::new();
+            options.dedup = Some(make_book::Dedup::Moves);
+            options.update_file = Some(update_file.to_str().unwrap().to_string());
+            uci_weights(&make_test_book("rschessbook_dedup", text, &mut options).0).into_iter()
+                .find(|e| e.0 == "e2e4").unwrap().1
+        };
+        assert_eq!(update(), 2);
+        assert_eq!(update(), 2);
+        std::fs::remove_file(&update_file).unwrap();
+    }
+
+    // 1. Nf3 e5 2. e4 transposes to 1. e4 e5 2. Nf3
+    const TRANSPOSED_GAMES: &str = concat!(
+        "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 Nc6 1-0\n\n",
+        "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 Nf6 1-0\n\n",
+        "[Result \"1/2-1/2\"]\n\n1. Nf3 e5 2. e4 Nc6 1/2-1/2\n\n",
+        "[Result \"0-1\"]\n\n1. d4 d5 0-1\n");
+
+    #[test]
+    fn test_dump_book() {
+        let mut options = make_book::SmakeOptions::new();
+        options.keep_zero = true;
+        let (mut entries, _) = make_test_book("rschessbook_dump", 
+            "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bc4 Nf6 4. O-O 1-0\n", &mut options);
+        assert_eq!(entries.len(), 7);
+
+        // an entry out of the lines of the book has neither san nor fen
+        entries.push(book::SfinalEntry::new(0x0123456789abcdef, 0x02db, 1, 0));
+
+        let text = dump_book::format_book(&entries, dump_book::DumpFormat::Text);
+        let line = |uci: &str| text.lines().find(|l| l.split_whitespace().nth(1) == Some(uci)).unwrap().to_string();
+        assert_eq!(line("e2e4"), 
+            "463b96181691fc9c e2e4      2          0 e4      rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(line("e7e5"), 
+            "823c9b50fd114196 e7e5      0          0 e5      rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        assert!(line("e1g1").ends_with(" O-O     r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4"));
+        assert_eq!(line("d2d4"), "0123456789abcdef d2d4      1          0");
+
+        let csv = dump_book::format_book(&entries, dump_book::DumpFormat::Csv);
+        assert_eq!(csv.lines().next(), Some("key,move,weight,learn,san,fen"));
+        assert!(csv.lines().any(|l| l.starts_with("463b96181691fc9c,e2e4,2,0,e4,rnbqkbnr/")));
+        assert_eq!(csv.lines().count(), 9);
+
+        let json = dump_book::format_book(&entries, dump_book::DumpFormat::Json);
+        assert!(json.starts_with("[\n  {\"key\": ") && json.ends_with("}\n]\n"));
+        assert!(json.contains("\"move\": \"g8f6\", \"weight\": 0, \"learn\": 0, \"san\": \"Nf6\""));
+        assert!(json.contains("\"san\": null, \"fen\": null}\n"));
+        assert_eq!(json.matches("},\n").count(), 7);
+    }
+
+    #[test]
+    fn test_book_to_pgn() {
+        // 1. Nf3 e5 2. e4 transposes to the main line
+        let mut options = make_book::SmakeOptions::new();
+        options.keep_zero = true;
+        let (entries, _) = make_test_book("rschessbook_book2pgn", TRANSPOSED_GAMES, &mut options);
+
+        let movetext = |fen: &str, max_ply: usize| -> String {
+            let text = book_pgn::book_to_pgn(&entries, fen, max_ply, "test");
+            assert!(text.starts_with("[Event \"test\"]\n"));
+            text.split("\n\n").nth(1).unwrap().replace('\n', " ").trim().to_string()
+        };
+        assert_eq!(movetext(util::START_FEN, usize::MAX), concat!(
+            "1. e4 {4 80.0%} (1. Nf3 {1 20.0%} 1... e5 {1 100.0%} 2. e4 {1 100.0%} {transposition}) ",
+            "(1. d4 {0 0.0%} 1... d5 {2 100.0%}) 1... e5 {0 0.0%} 2. Nf3 {4 100.0%} ",
+            "2... Nc6 {1 100.0%} (2... Nf6 {0 0.0%}) *"));
+        assert_eq!(movetext(util::START_FEN, 1), "1. e4 {4 80.0%} (1. Nf3 {1 20.0%}) (1. d4 {0 0.0%}) *");
+
+        let fen = "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2";
+        let text = book_pgn::book_to_pgn(&entries, fen, usize::MAX, "test");
+        assert!(text.contains("[SetUp \"1\"]\n[FEN \"rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2\"]\n"));
+        assert_eq!(movetext(fen, usize::MAX), "2... Nc6 {1 100.0%} (2... Nf6 {0 0.0%}) *");
+    }
+
+    #[test]
+    fn test_book_to_dot() {
+        // 1. Nf3 e5 2. e4 reaches the same node as 1. e4 e5 2. Nf3
+        let mut options = make_book::SmakeOptions::new();
+        options.keep_zero = true;
+        let (entries, _) = make_test_book("rschessbook_book2dot", TRANSPOSED_GAMES, &mut options);
+
+        let mut dot_options = book_dot::SdotOptions::new();
+        let text = book_dot::book_to_dot(&entries, &dot_options);
+        assert!(text.starts_with("digraph book {\n"));
+        assert!(text.ends_with("}\n"));
+        assert!(text.contains("    n463b96181691fc9c [label=\"start\"];\n"));
+        assert!(text.contains(" [label=\"e4\\n4 (80.0%)\", penwidth=4.2];\n"));
+        assert_eq!(text.matches("[label=\"1. e4 e5 2. Nf3\"]").count(), 1);
+        assert_eq!(text.matches("[label=\"1. Nf3 e5 2. e4\"]").count(), 0);
+        assert_eq!(text.matches("[label=\"Nf3\\n4 (100.0%)\"").count(), 1);
+        assert_eq!(text.matches("[label=\"e4\\n1 (100.0%)\"").count(), 1);
+        assert_eq!(text.matches(" -> ").count(), 10);
+
+        // only the moves of 20% and the first two plies
+        dot_options.max_ply = 2;
+        dot_options.min_share = 20.0;
+        let text = book_dot::book_to_dot(&entries, &dot_options);
+        assert_eq!(text.matches(" -> ").count(), 3);
+        assert!(!text.contains("d4"));
+
+        dot_options.fen = String::from("rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+        dot_options.label = book_dot::DotLabel::Fen;
+        let text = book_dot::book_to_dot(&entries, &dot_options);
+        assert!(text.contains("[label=\"rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b\"];\n"));
+        assert!(text.contains("[label=\"r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w\"];\n"));
+        assert_eq!(text.matches(" -> ").count(), 1);
+    }
+
+    #[test]
+    fn test_book_stats() {
+        let mut options = make_book::SmakeOptions::new();
+        options.keep_zero = true;
+        let (mut entries, _) = make_test_book("rschessbook_book_stats", TRANSPOSED_GAMES, &mut options);
+
+        let stats = book_stats::compute_stats(&entries);
+        assert_eq!((stats.entries, stats.positions, stats.max_moves), (10, 7, 3));
+        assert_eq!((stats.weight_min, stats.weight_max, stats.weight_sum, stats.weight_median), (0, 4, 14, 1));
+        assert_eq!(stats.weight_groups, [3, 4, 3, 0, 0, 0]);
+        assert_eq!(stats.plies.iter().map(|(p, n)| (*p, *n)).collect::<Vec<(usize, (usize, usize))>>(),
+            [(0, (1, 3)), (1, (3, 3)), (2, (2, 2)), (3, (1, 2))]);
+        assert_eq!((stats.unreachable_entries, stats.illegal_moves, stats.duplicates), (0, 0, 0));
+
+        // a repeated entry, an illegal move in the initial position and a position not reached
+        let first = entries.iter().find(|e| e.key == 0x463b96181691fc9c).unwrap().clone();
+        entries.push(first.clone());
+        entries.push(book::SfinalEntry::new(first.key, 0x0e1c, 1, 0));
+        entries.push(book::SfinalEntry::new(0x1234, first.move_, 1, 0));
+        let stats = book_stats::compute_stats(&entries);
+        assert_eq!((stats.unreachable_entries, stats.unreachable_positions), (1, 1));
+        assert_eq!((stats.illegal_moves, stats.duplicates), (1, 1));
+        let text = book_stats::format_stats(&stats);
+        assert!(text.starts_with("entries               13\npositions             8\n"));
+        assert!(text.contains("\nply 0                 5 entries in 1 positions\n"));
+        assert!(text.ends_with("\nunreachable           1 entries in 1 positions\nillegal moves         1\nduplicates            1\n"));
+    }
+
+    #[test]
+    fn test_check_book() {
+        let mut options = make_book::SmakeOptions::new();
+        options.keep_zero = true;
+        let (entries, _) = make_test_book("rschessbook_check_book", concat!(
+            "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 Nc6 1-0\n\n",
+            "[Result \"1/2-1/2\"]\n\n1. Nf3 e5 2. e4 Nc6 1/2-1/2\n\n",
+            "[Result \"0-1\"]\n\n1. d4 d5 0-1\n"), &mut options);
+        let bin_file = std::env::temp_dir().join("rschessbook_check_book.bin");
+        let bin_name = bin_file.to_str().unwrap();
+        book::write_book_file(bin_name, &entries).unwrap();
+        assert!(book_check::check_book_file(bin_name).unwrap().is_valid());
+
+        // reversed, with a repeated entry, an illegal move, a position 
+        // not reached and an incomplete entry at the end
+        let start = entries.iter().find(|e| e.key == 0x463b96181691fc9c).unwrap().clone();
+        let mut broken: Vec<book::SfinalEntry> = entries.iter().rev().cloned().collect();
+        broken.push(start.clone());
+        broken.push(book::SfinalEntry::new(start.key, 0x0e1c, 1, 0));
+        broken.push(book::SfinalEntry::new(0x1234, start.move_, 1, 0));
+        book::write_book_file(bin_name, &broken).unwrap();
+        let mut bytes = std::fs::read(&bin_file).unwrap();
+        bytes.extend_from_slice(&[0, 1, 2]);
+        std::fs::write(&bin_file, bytes).unwrap();
+
+        let report = book_check::check_book_file(bin_name).unwrap();
+        assert!(!report.is_valid());
+        assert_eq!((report.entries, report.trailing_bytes, report.duplicates, report.illegal, report.unreachable),
+            (entries.len() + 3, 3, 1, 1, 1));
+        assert!(report.unsorted > 0);
+        assert!(report.messages.contains(&format!("entry {} (463b96181691fc9c {}): duplicate of entry {}", 
+            entries.len(), moves::get_uci_format(start.move_).trim(), 
+            broken.iter().position(|e| *e == start).unwrap())));
+        assert!(report.messages.iter().any(|m| m.ends_with(": illegal move in the position")));
+
+        // the repeated entry adds its weight
+        let repaired = book_check::repair_entries(&broken);
+        let mut expected = entries.clone();
+        expected.iter_mut().find(|e| **e == start).unwrap().weight *= 2;
+        expected.sort_by(|a, b| a.key.cmp(&b.key).then(b.weight.cmp(&a.weight)).then(a.move_.cmp(&b.move_)));
+        assert_eq!(repaired, expected);
+        assert!(book_check::check_entries(&repaired, 0).is_valid());
+
+        std::fs::remove_file(&bin_file).unwrap();
+    }
+
+    #[test]
+    fn test_prune_book() {
+        let mut options = make_book::SmakeOptions::new();
+        options.keep_zero = true;
+        let (mut entries, _) = make_test_book("rschessbook_prune_book", concat!(
+            "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 Nc6 1-0\n\n",
+            "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 Nc6 1-0\n\n",
+            "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 Nc6 1-0\n\n",
+            "[Result \"0-1\"]\n\n1. e4 c5 0-1\n"), &mut options);
+
+        // the entries of 3. d4 exd4 4. Nxd4, only reached from a root, and of a lone pawn
+        let root = "r1bqkbnr/pppp1ppp/2n5/4p3/3PP3/5N2/PPP2PPP/RNBQKB1R b KQkq - 0 3";
+        let entry = |fen: &str, uci: &str, weight: u16| -> book::SfinalEntry {
+            let mut board: board::Sboard = board::Sboard::new();
+            let mut moves: Vec<moves::Smove> = Vec::new();
+            assert!(board.set_fen(fen));
+            board.gen_moves(&mut moves);
+            let m = moves.iter().find(|m| m.to_uci() == uci).unwrap();
+            book::SfinalEntry::new(board.hash_key, m.encoded_move, weight, 0)
+        };
+        entries.push(entry(root, "e5d4", 2));
+        entries.push(entry("r1bqkbnr/pppp1ppp/2n5/8/3pP3/5N2/PPP2PPP/RNBQKB1R w KQkq - 0 4", "f3d4", 2));
+        entries.push(entry("8/8/8/4k3/8/8/4P3/4K3 w - - 0 1", "e2e4", 2));
+        entries.sort_by_key(|e| e.key);
+
+        let uci = |pruned: &[book::SfinalEntry]| -> Vec<String> {
+            let mut moves: Vec<String> = pruned.iter().map(|e| moves::get_uci_format(e.move_).trim().to_string()).collect();
+            moves.sort();
+            moves
+        };
+        let mut prune_options = book_prune::SpruneOptions::new();
+        let pruned = book_prune::prune_entries(&entries, &prune_options);
+        assert_eq!(uci(&pruned), ["b8c6", "c7c5", "e2e4", "e7e5", "g1f3"]);
+        assert!(pruned.windows(2).all(|w| w[0].key <= w[1].key));
+
+        assert!(prune_options.add_roots(&format!("\n{}\n\n", root)));
+        assert!(!prune_options.add_roots("8/8/8/8 w - - 0 1"));
+        assert!(!prune_options.add_roots("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0"));
+        assert_eq!(uci(&book_prune::prune_entries(&entries, &prune_options)), 
+            ["b8c6", "c7c5", "e2e4", "e5d4", "e7e5", "f3d4", "g1f3"]);
+
+        // e5 lost every game (weight 0) and Nf3 is 2 half-moves from the start
+        prune_options.max_ply = 2;
+        prune_options.min_share = 30.0;
+        assert_eq!(uci(&book_prune::prune_entries(&entries, &prune_options)), 
+            ["c7c5", "e2e4", "e5d4", "f3d4"]);
+    }
+
+    #[test]
+    fn test_edit_book() {
+        let bin_file = std::env::temp_dir().join("rschessbook_edit_book.bin");
+        let after_e4 = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        let castle = "r3k2r/pppq1ppp/2n2n2/3pp3/3PP3/2N2N2/PPPQ1PPP/R3K2R w KQkq - 0 8";
+        let mut book = book_edit::SeditableBook::new(Vec::new());
+
+        assert_eq!(book.add_move(util::START_FEN, "e4", 10), Ok(()));
+        assert_eq!(book.add_move(util::START_FEN, "d2d4", 5), Ok(()));
+        assert_eq!(book.add_move(after_e4, "c5", 3), Ok(()));
+        assert_eq!(book.add_move(after_e4, "e5", 4), Ok(()));
+        assert_eq!(book.add_move(castle, "O-O", 1), Ok(()));
+        assert_eq!(book.add_move(util::START_FEN, "e4", 1), Err(book_edit::EditError::MoveInBook));
+        assert_eq!(book.add_move(util::START_FEN, "e5", 1), Err(book_edit::EditError::Move(san::SanError::NoSuchMove)));
+        assert_eq!(book.add_move("8/8 w", "e4", 1), Err(book_edit::EditError::InvalidFen("8/8 w".to_string())));
+        let five_fields = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0";
+        assert_eq!(book.add_move(five_fields, "e4", 1), Err(book_edit::EditError::InvalidFen(five_fields.to_string())));
+        assert_eq!(book.add_move("8/8/8/8/8/8/8/8 w - - x 1", "e4", 1),
+            Err(book_edit::EditError::InvalidFen("8/8/8/8/8/8/8/8 w - - x 1".to_string())));
+
+        assert_eq!(book.set_weight(after_e4, "c5", 8), Ok(()));
+        assert_eq!(book.set_weight(after_e4, "d5", 8), Err(book_edit::EditError::MoveNotInBook));
+        assert_eq!(book.remove_move(util::START_FEN, "d4"), Ok(()));
+        assert_eq!(book.remove_move(util::START_FEN, "d4"), Err(book_edit::EditError::MoveNotInBook));
+
+        // saved sorted, with the polyglot encoding of castling
+        book.save(bin_file.to_str().unwrap()).unwrap();
+        let mut book = book_edit::SeditableBook::load(bin_file.to_str().unwrap()).unwrap();
+        assert!(book.entries.windows(2).all(|w| (w[0].key, u16::MAX - w[0].weight) < (w[1].key, u16::MAX - w[1].weight)));
+        assert_eq!(uci_weights(&book::read_book_file(bin_file.to_str().unwrap()).unwrap()), 
+            [("c7c5".to_string(), 8), ("e1h1".to_string(), 1), ("e2e4".to_string(), 10), ("e7e5".to_string(), 4)]);
+
+        // e2e4 is also the pawn move of the position after 1. d4 d5, and 
+        // the queen move of 1. e4 d5 2. exd5 Qxd5 3. Qe2 Nc6 4. Qe4
+        assert_eq!(book.add_move(util::START_FEN, "d4", 1), Ok(()));
+        assert_eq!(book.add_move("rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq d3 0 1", "d5", 1), Ok(()));
+        assert_eq!(book.add_move("rnbqkbnr/ppp1pppp/8/3p4/3P4/8/PPP1PPPP/RNBQKBNR w KQkq d6 0 2", "e4", 2), Ok(()));
+        let mut board: board::Sboard = board::Sboard::new();
+        assert!(board.set_fen(after_e4));
+        for san in ["d5", "exd5", "Qxd5", "Qe2", "Nc6", "Qe4"].iter() {
+            assert_eq!(book.add_move(board.get_fen().as_str(), san, 1), Ok(()));
+            let mut m = san::resolve_san(san, &mut board).unwrap();
+            board.make_move(&mut m);
+        }
+        assert_eq!(book.forbid_move(util::START_FEN, "e4"), Ok(2));
+        assert_eq!(book.entries.len(), 11);
+        assert_eq!(book.entries.iter().filter(|e| moves::get_uci_format(e.move_).trim() == "e2e4").count(), 1);
+
+        std::fs::remove_file(&bin_file).unwrap();
+    }
 }
\ No newline at end of file
@@ -214,6 +214,23 @@ impl Smove {
                         to_row_bin | to_file_bin;
         self.encoded_move = mov;
     }
+
+    /* UCI notation as used by the engines (e1g1 for castles, not e1h1) */
+    pub fn to_uci(&self) -> String {
+        let promote_pieces: [char; 5]= [' ', 'n', 'b', 'r', 'q'];
+
+        if self.tipe == MOVE_TYPE_NONE {
+            return String::from("0000");      // null move
+        }
+
+        let mut move_s: String = String::new();
+        move_s.push_str(square::ALGEBRA_SQUARE[self.from]);
+        move_s.push_str(square::ALGEBRA_SQUARE[self.dest]);
+        if self.promotion_poly != PROMOTION_POLY_NONE {
+            move_s.push(promote_pieces[self.promotion_poly]);
+        }
+        move_s
+    }
 }
 
 
@@ -224,6 +241,9 @@ pub struct Shist {
     pub m: Smove,
     pub castle: usize,
     pub cap: usize,
+    pub ply_pawn: usize,        /* values before the move, to restore them in undo */
+    pub passant: i16,
+    pub hash: u64,
 }
 
 impl Shist {
@@ -232,6 +252,9 @@ impl Shist {
             m: Smove::new(),
             castle: 0,
             cap: 0,
+            ply_pawn: 0,
+            passant: -1,
+            hash: 0,
         }
     }
 }
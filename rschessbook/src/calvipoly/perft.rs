@@ -1,7 +1,66 @@
 use std::time::{Instant};
+use std::fs;
+use std::mem;
+use std::thread;
 
 use super::board;
 use super::moves;
+use super::util;
+
+
+/* An entry of the perft hash table: nodes below a position at a depth */
+#[derive(Clone)]
+pub struct SperftEntry {
+    pub key  : u64,
+    pub depth: i32,
+    pub nodes: u64,
+}
+
+impl SperftEntry {
+    pub fn new() -> Self {
+        SperftEntry {
+            key  : 0,
+            depth: -1,
+            nodes: 0,
+        }
+    }
+}
+
+
+/* Fixed size table, always replace */
+pub struct SperftHash {
+    entries: Vec<SperftEntry>,
+}
+
+impl SperftHash {
+    pub fn new(size: usize) -> Self {
+        SperftHash {
+            entries: vec![SperftEntry::new(); size],
+        }
+    }
+
+    /* number of entries that fit in hash_mb megabytes */
+    pub fn entries_for(hash_mb: usize) -> usize {
+        hash_mb * 1024 * 1024 / mem::size_of::<SperftEntry>()
+    }
+
+    pub fn probe(&self, key: u64, depth: i32) -> Option<u64> {
+        if self.entries.is_empty() { return None; }
+
+        let entry = &self.entries[(key % self.entries.len() as u64) as usize];
+        if entry.key == key && entry.depth == depth {
+            return Some(entry.nodes);
+        }
+        None
+    }
+
+    pub fn store(&mut self, key: u64, depth: i32, nodes: u64) {
+        if self.entries.is_empty() { return; }
+
+        let idx = (key % self.entries.len() as u64) as usize;
+        self.entries[idx] = SperftEntry { key, depth, nodes };
+    }
+}
 
 
 pub struct Perft {
@@ -11,31 +70,252 @@ pub struct Perft {
 
 impl Perft {
     pub fn perft (board: &mut board::Sboard, depth: i32) {
+        Perft::perft_threads(board, depth, 1, 0);
+    }
+
+
+    /* The root moves are shared among the threads, 
+    *  every thread with its own table of hash_mb / threads megabytes */
+    pub fn perft_threads (board: &mut board::Sboard, depth: i32, 
+            threads: usize, hash_mb: usize) {
         let mut time: u128;
+        let start = Instant::now();
+
+        let threads = if threads < 1 { 1 } else { threads };
+        let entries = SperftHash::entries_for(hash_mb) / threads;
+        let mut tables: Vec<SperftHash> = Vec::new();
+        for _ in 0..threads {
+            tables.push(SperftHash::new(entries));
+        }
         let mut now = Instant::now();
         
         {
-            println!("depth\ttime (MiliSecs.)\t\t\tnodes");
-            println!("-----\t----------------\t\t\t-----");
+            println!("depth\ttime (MiliSecs.)\t\t\tnodes\t\tnodes/sec");
+            println!("-----\t----------------\t\t\t-----\t\t---------");
             for i in 1..depth+1 {
                 
-                let nodes = Perft::mini_max(board, i);
+                let nodes = if threads <= 1 && hash_mb == 0 {
+                    Perft::mini_max(board, i)
+                }
+                else {
+                    Perft::count_parallel(board, i, &mut tables)
+                };
                 time = now.elapsed().as_millis();
-                println!("{}\t{}\t\t\t\t\t{}", i, time, nodes);
+                println!("{}\t{}\t\t\t\t\t{}\t\t{}", i, time, nodes, 
+                    nodes_per_second(nodes, now));
                 now = Instant::now();
                 
                 //board.clone().print_board();
             }
         }
         
-        time = now.elapsed().as_millis();    // it throws u128
+        time = start.elapsed().as_millis();    // it throws u128
         println!("Time used: {}", time);
         
     }
+
+
+    /* One thread for every table, the tables are kept for the next call */
+    pub fn count_parallel (board: &mut board::Sboard, depth: i32, 
+            tables: &mut Vec<SperftHash>) -> u64 {
+        
+        if depth == 0 { return 1; }
+
+        assert!(!tables.is_empty());
+        let threads = tables.len();
+
+        // legal moves of the root, dealt out among the threads
+        let mut moves: Vec<moves::Smove> = Vec::new();
+        let num_moves = board.gen_moves(&mut moves);
+        let mut shares: Vec<Vec<moves::Smove>> = vec![Vec::new(); threads];
+        let mut legal: usize = 0;
+
+        for i in 0..num_moves {
+            let tmp = board.make_move(&mut moves[i as usize]);
+            board.undo_move(&mut moves[i as usize]);
+            if tmp.is_some() {
+                shares[legal % threads].push(moves[i as usize].clone());
+                legal += 1;
+            }
+        }
+
+        let mut handles = Vec::new();
+        for (share, mut hash) in shares.into_iter().zip(tables.drain(..)) {
+            let mut clon = board.clone();
+            handles.push(thread::spawn(move || {
+                let mut nodes: u64 = 0;
+                for mut m in share.into_iter() {
+                    clon.make_move(&mut m);
+                    nodes += Perft::mini_max_hash(&mut clon, depth-1, &mut hash);
+                    clon.undo_move(&mut m);
+                }
+                (nodes, hash)
+            }));
+        }
+
+        let mut nodes: u64 = 0;
+        for handle in handles.into_iter() {
+            let (count, hash) = handle.join().unwrap();
+            nodes += count;
+            tables.push(hash);
+        }
+        nodes
+    }
+
+
+    fn mini_max_hash (board: &mut board::Sboard, depth: i32, 
+            hash: &mut SperftHash) -> u64 {
+        let mut nodes: u64 = 0;
+
+        if depth == 0 { return 1; }
+        // the last ply is cheaper to count than to look up
+        if depth == 1 { return Perft::mini_max(board, 1); }
+
+        let key = board.hash_key;
+        if let Some(nodes) = hash.probe(key, depth) {
+            return nodes;
+        }
+
+        let mut moves: Vec<moves::Smove> = Vec::new();
+        let num_moves = board.gen_moves(&mut moves);
+
+        for i in 0..num_moves {
+            let tmp = board.make_move(&mut moves[i as usize]);
+            if tmp.is_none() { 
+                board.undo_move(&mut moves[i as usize]);
+                continue;
+            }
+
+            nodes += Perft::mini_max_hash(board, depth-1, hash);
+
+            board.undo_move(&mut moves[i as usize]);
+        }
+
+        hash.store(key, depth, nodes);
+        nodes
+    }
     
     
     
-    fn mini_max (board: &mut board::Sboard, depth: i32) -> u64 {
+    /* Nodes below every legal move of the root, 
+    to find which move differs from other engines */
+    pub fn divide (board: &mut board::Sboard, depth: i32) -> u64 {
+        let now = Instant::now();
+        let mut total: u64 = 0;
+        let mut legal: i32 = 0;
+
+        assert!(depth >= 1);
+
+        let mut moves: Vec<moves::Smove> = Vec::new();
+        let num_moves = board.gen_moves(&mut moves);
+
+        for i in 0..num_moves {
+            let tmp = board.make_move(&mut moves[i as usize]);
+            if tmp.is_none() { 
+                board.undo_move(&mut moves[i as usize]);
+                continue;
+            }
+
+            let nodes = Perft::mini_max(board, depth-1);
+            board.undo_move(&mut moves[i as usize]);
+
+            println!("{}\t{}", moves[i as usize].to_uci(), nodes);
+            total += nodes;
+            legal += 1;
+        }
+
+        println!();
+        println!("moves\t{}", legal);
+        println!("nodes\t{}", total);
+        println!("Time used: {}", now.elapsed().as_millis());
+
+        total
+    }
+
+
+    /* Runs an EPD file with lines like
+    *  "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - ;D1 20 ;D2 400"
+    *  Returns the number of positions passed and the total */
+    pub fn perft_suite (epd_file: &str, max_depth: i32) -> (i32, i32) {
+        let now = Instant::now();
+        let mut passed: i32 = 0;
+        let mut total: i32 = 0;
+
+        let content = fs::read_to_string(epd_file);
+        if content.is_err() {
+            let tmp = format!("perft_suite(): can't open file \"{}\"\n", epd_file);
+            util::my_fatal(tmp.as_str());
+        }
+
+        for line in content.unwrap().lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split(';').collect();
+            let mut fen = fields[0].trim().to_string();
+            // EPD has not the move counters
+            if fen.split_whitespace().count() == 4 {
+                fen.push_str(" 0 1");
+            }
+
+            total += 1;
+            println!("#{} {}", total, fen);
+
+            let mut board: board::Sboard = board::Sboard::new();
+            if !board.set_fen(fen.as_str()) {
+                println!("\tinvalid FEN\tFAILED");
+                continue;
+            }
+
+            let mut ok = true;
+            for field in fields[1..].iter() {
+                let parts: Vec<&str> = field.split_whitespace().collect();
+                if parts.len() != 2 || !parts[0].starts_with('D') {
+                    continue;
+                }
+                let depth = parts[0][1..].parse::<i32>();
+                let expected = parts[1].parse::<u64>();
+                if depth.is_err() || expected.is_err() {
+                    println!("\tmalformed field \"{}\"\tFAILED", field.trim());
+                    ok = false;
+                    continue;
+                }
+                let depth = depth.unwrap();
+                let expected = expected.unwrap();
+                if depth > max_depth {
+                    continue;
+                }
+
+                let nodes = Perft::mini_max(&mut board, depth);
+                if nodes == expected {
+                    println!("\tD{}\t{}\tok", depth, nodes);
+                }
+                else {
+                    println!("\tD{}\t{}\tFAILED (expected {})", depth, nodes, expected);
+                    ok = false;
+                }
+            }
+
+            if ok {
+                passed += 1;
+                println!("\tpass");
+            }
+            else {
+                println!("\tfail");
+            }
+        }
+
+        println!("passed {} of {} positions", passed, total);
+        println!("Time used: {}", now.elapsed().as_millis());
+
+        (passed, total)
+    }
+
+
+
+    pub fn mini_max (board: &mut board::Sboard, depth: i32) -> u64 {
         let mut nodes: u64 = 0;
 
         if depth == 0 { return 1; }
@@ -111,4 +391,14 @@ impl Perft {
         return nodes;
     }
     */
+}
+
+
+
+fn nodes_per_second(nodes: u64, since: Instant) -> u64 {
+    let secs = since.elapsed().as_secs_f64();
+    if secs <= 0.0 {
+        return 0;
+    }
+    (nodes as f64 / secs) as u64
 }
\ No newline at end of file
@@ -1,7 +1,7 @@
 use std::io::prelude::*;
 use std::fs::File;
 
-use super::util;
+use super::{util, san};
 
 const PGN_STRING_SIZE: i32 = 256;
 
@@ -13,7 +13,7 @@ const DISP_CHAR: bool = false;
 
 const TAB_SIZE: i32 = 8;
 
-const CHAR_EOF: i32 = 256;
+const CHAR_EOF: i32 = -1;         // the characters are unicode code points
 
 // types
 #[derive(PartialEq, Debug)]
@@ -29,10 +29,78 @@ pub enum TokenT {
 
 
 
+// how the bytes of the file are read
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Encoding {
+    Utf8,
+    Latin1,
+    Windows1252,
+}
+
+// the characters 0x80 - 0x9F of windows-1252 (0 if they are undefined)
+const WINDOWS_1252: [u32; 32] = [
+    0x20AC, 0, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021, 
+    0x02C6, 0x2030, 0x0160, 0x2039, 0x0152, 0, 0x017D, 0,
+    0, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014, 
+    0x02DC, 0x2122, 0x0161, 0x203A, 0x0153, 0, 0x017E, 0x0178,
+];
+
+
+/* The encoding of the file: utf-8, with the fallback for the bytes 
+*  that are not utf-8 (files from several countries are mixed), or 
+*  only single bytes in latin-1 or windows-1252. 
+*  A file that begins with the utf-8 BOM is always utf-8 */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SpgnEncoding {
+    pub encoding: Encoding,
+    pub fallback: Encoding,
+}
+
+impl SpgnEncoding {
+    pub fn new() -> Self {
+        SpgnEncoding {
+            encoding: Encoding::Utf8,
+            fallback: Encoding::Windows1252,
+        }
+    }
+
+    // set_encoding()
+    // false if the name is unknown
+
+    pub fn set_encoding(&mut self, name: &str) -> bool {
+        match encoding_from_name(name) {
+            Some(encoding) => {
+                self.encoding = encoding;
+                true
+            },
+            None => false,
+        }
+    }
+
+    // set_fallback()
+    // the fallback is a single byte encoding
+
+    pub fn set_fallback(&mut self, name: &str) -> bool {
+        match encoding_from_name(name) {
+            Some(Encoding::Utf8) | None => false,
+            Some(encoding) => {
+                self.fallback = encoding;
+                true
+            },
+        }
+    }
+}
+
+
+
 pub struct Spgn {
     file: Option<std::io::Result<File>>,
+    pub encoding: SpgnEncoding,
+    utf8: bool,
 
     char_hack: i32,
+    bytes_unread: Vec<u8>,
     char_line: i32,
     char_column: i32,
     char_unread: bool,
@@ -48,6 +116,7 @@ pub struct Spgn {
 
     result: String,
     fen: String,
+    tags: Vec<(String, String)>,
 
     pub move_line: i32, 
     pub move_column: i32,
@@ -59,8 +128,11 @@ impl Spgn {
     pub fn new() -> Self {
         Spgn {
             file: None,
+            encoding: SpgnEncoding::new(),
+            utf8: true,
 
             char_hack: CHAR_EOF,
+            bytes_unread: Vec::new(),
             char_line: 1,
             char_column: 0,
             char_unread: false,
@@ -76,6 +148,7 @@ impl Spgn {
 
             result: String::with_capacity(PGN_STRING_SIZE as usize),
             fen: String::with_capacity(PGN_STRING_SIZE as usize),
+            tags: Vec::new(),
 
             move_line: -1,
             move_column: -1,
@@ -96,6 +169,24 @@ impl Spgn {
         self.result.clone()
     }
 
+    // get the FEN tag of the game ("" if there is not one)
+
+    pub fn get_fen(&mut self) -> String {
+        self.fen.clone()
+    }
+
+    // get the value of a tag of the game (None if the game has not it)
+
+    pub fn get_tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+
+    // all the tags of the game, in the order of the file
+
+    pub fn get_tags(&self) -> &[(String, String)] {
+        &self.tags
+    }
+
 
     // pgn_open()
 
@@ -113,6 +204,7 @@ impl Spgn {
         self.file = Some(file);
 
         self.char_hack = CHAR_EOF; // DEBUG
+        self.bytes_unread.clear();
         self.char_line = 1;
         self.char_column = 0;
         self.char_unread = false;
@@ -131,6 +223,39 @@ impl Spgn {
 
         self.move_line = -1; // DEBUG
         self.move_column = -1; // DEBUG
+
+        self.pgn_skip_bom(file_name);
+    }
+
+
+    // pgn_skip_bom()
+    // the byte order mark is not part of the text
+
+    fn pgn_skip_bom(&mut self, file_name: &str) {
+
+        let mut bytes: Vec<u8> = Vec::new();
+
+        self.utf8 = self.encoding.encoding == Encoding::Utf8;
+
+        while bytes.len() < 3 {
+            match self.pgn_byte_read() {
+                Some(byte) => bytes.push(byte),
+                None => break,
+            }
+        }
+
+        if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+            self.utf8 = true;
+            return;
+        }
+        if bytes.starts_with(&[0xFF, 0xFE]) || bytes.starts_with(&[0xFE, 0xFF]) {
+            let tmp = format!("pgn_open(): UTF-16 is not supported in file \"{}\"\n", file_name);
+            util::my_fatal(tmp.as_str());
+        }
+
+        while let Some(byte) = bytes.pop() {
+            self.bytes_unread.push(byte);
+        }
     }
 
 
@@ -145,12 +270,13 @@ impl Spgn {
     
         self.result = String::from("*");
         self.fen = String::from("");
+        self.tags.clear();
     
         // loop
     
         loop {
             self.pgn_token_read();
-            if self.token_type as u8 as char != '[' { break; }
+            if self.token_type != '[' as i32 { break; }
 
             // tag
             
@@ -175,13 +301,15 @@ impl Spgn {
             value = self.token_string.clone();
     
             self.pgn_token_read();
-            if self.token_type as u8 as char != ']' {
+            if self.token_type != ']' as i32 {
                 let tmp = format!(
                     "pgn_next_game(): malformed tag at line {}, column {}, game {}\n",
                     self.token_line, self.token_column, self.game_nb);
                 util::my_fatal(tmp.as_str());
             }
 
+            self.tags.push((name.clone(), value.clone()));
+
             // special tag?
             
             if name == "Result" {
@@ -278,28 +406,28 @@ impl Spgn {
             self.token_type = TokenT::TokenEof as i32;
     
         }
-        else if ".[]()<>".contains(self.char_hack as u8 as char) {
+        else if ".[]()<>".contains(self.hack_char()) {
             // single-character token
             self.token_type = self.char_hack;
             //sprintf(pgn->token_string,"%c",pgn->char_hack);
             self.token_string.clear();
-            self.token_string.push(self.char_hack as u8 as char);
+            self.token_string.push(self.hack_char());
             self.token_length = 1;
     
         } 
-        else if self.char_hack as u8 as char == '*' {
+        else if self.hack_char() == '*' {
     
             self.token_type = TokenT::TokenResult as i32;
             self.token_string.clear();
-            self.token_string.push(self.char_hack as u8 as char);
+            self.token_string.push(self.hack_char());
             self.token_length = 1;
     
         } 
-        else if self.char_hack as u8 as char == '!' {
+        else if self.hack_char() == '!' {
     
             self.pgn_char_read();
             
-            if self.char_hack as u8 as char == '!' { // "!!"
+            if self.hack_char() == '!' { // "!!"
         
                 self.token_type = TokenT::TokenNag as i32;
                 self.token_string.clear();
@@ -307,7 +435,7 @@ impl Spgn {
                 self.token_length = 1;
         
             } 
-            else if self.char_hack as u8 as char == '?' { // "!?"
+            else if self.hack_char() == '?' { // "!?"
         
                 self.token_type = TokenT::TokenNag as i32;
                 self.token_string.clear();
@@ -326,11 +454,11 @@ impl Spgn {
             }
     
         }
-        else if self.char_hack as u8 as char == '?' {
+        else if self.hack_char() == '?' {
     
             self.pgn_char_read();
     
-            if self.char_hack as u8 as char == '?' { // "??"
+            if self.hack_char() == '?' { // "??"
         
                 self.token_type = TokenT::TokenNag as i32;
                 self.token_string.clear();
@@ -338,7 +466,7 @@ impl Spgn {
                 self.token_length = 1;
         
             } 
-            else if self.char_hack as u8 as char == '!' { // "?!"
+            else if self.hack_char() == '!' { // "?!"
         
                 self.token_type = TokenT::TokenNag as i32;
                 self.token_string.clear();
@@ -357,7 +485,28 @@ impl Spgn {
             }
         
         } 
-        else if is_symbol_start(self.char_hack) {
+        else if self.hack_char() == '-' {
+
+            // null move "--"
+
+            self.pgn_char_read();
+
+            if self.hack_char() != '-' {
+                let tmp = format!(
+                    "pgn_read_token(): illegal character '-' at line {}, column {},game {}\n",
+                    self.token_line,
+                    self.token_column,
+                    self.game_nb);
+                util::my_fatal(tmp.as_str());
+            }
+
+            self.token_type = TokenT::TokenSymbol as i32;
+            self.token_string.clear();
+            self.token_string.push_str("--");
+            self.token_length = 2;
+
+        }
+        else if is_symbol_start(self.hack_char()) {
             
             // symbol, integer, or result
         
@@ -375,17 +524,18 @@ impl Spgn {
                     util::my_fatal(tmp.as_str());
                 }
         
-                if !(self.char_hack as u8 as char).is_digit(10) { 
+                if !self.hack_char().is_ascii_digit() { 
                     self.token_type = TokenT::TokenSymbol as i32; 
                 }
         
                 //pgn->token_string[pgn->token_length++] = pgn->char_hack;
-                self.token_string.push(self.char_hack as u8 as char);
+                self.token_string.push(self.hack_char());
                 self.token_length += 1;
 
                 self.pgn_char_read();
 
-                if !is_symbol_next(self.char_hack) { break; }
+                if !is_symbol_next(self.hack_char()) && 
+                        !is_ep_next(self.hack_char(), &self.token_string) { break; }
             }
     
             self.pgn_char_unread();
@@ -408,7 +558,7 @@ impl Spgn {
             }
     
         }
-        else if self.char_hack as u8 as char == '"' {
+        else if self.hack_char() == '"' {
     
         // string
     
@@ -428,9 +578,9 @@ impl Spgn {
                     util::my_fatal(tmp.as_str());
                 }
         
-                if self.char_hack as u8 as char == '"' { break; }
+                if self.hack_char() == '"' { break; }
         
-                if self.char_hack as u8 as char == '\\' {
+                if self.hack_char() == '\\' {
         
                     self.pgn_char_read();
         
@@ -441,8 +591,8 @@ impl Spgn {
                         util::my_fatal(tmp.as_str());
                     }
         
-                    if self.char_hack as u8 as char != '"' && 
-                            self.char_hack as u8 as char != '\\' {
+                    if self.hack_char() != '"' && 
+                            self.hack_char() != '\\' {
         
                         // bad escape, ignore
         
@@ -454,7 +604,7 @@ impl Spgn {
                         }
         
                         //pgn.token_string[pgn->token_length++] = '\\';
-                        self.token_string.push(self.char_hack as u8 as char);
+                        self.token_string.push(self.hack_char());
                         self.token_length += 1;
                     }
                 }
@@ -467,13 +617,13 @@ impl Spgn {
                 }
         
                 //pgn->token_string[pgn->token_length++] = pgn->char_hack;
-                self.token_string.push(self.char_hack as u8 as char);
+                self.token_string.push(self.hack_char());
                 self.token_length += 1;
             }
         
             assert!(self.token_length >= 0 && self.token_length < PGN_STRING_SIZE);
         }
-        else if self.char_hack as u8 as char == '$' {
+        else if self.hack_char() == '$' {
     
             // NAG
         
@@ -484,7 +634,7 @@ impl Spgn {
         
                 self.pgn_char_read();
         
-                if !(self.char_hack as u8 as char).is_digit(10) { break; }
+                if !self.hack_char().is_ascii_digit() { break; }
         
                 if self.token_length >= 3 {
                     let tmp = format!(
@@ -494,7 +644,7 @@ impl Spgn {
                 }
         
                 //pgn->token_string[pgn->token_length++] = pgn->char_hack;
-                self.token_string.push(self.char_hack as u8 as char);
+                self.token_string.push(self.hack_char());
                 self.token_length += 1;
             }
         
@@ -532,12 +682,12 @@ impl Spgn {
             if self.char_hack==CHAR_EOF { 
                 break;
             } 
-            else if char::is_ascii_whitespace(&(self.char_hack as u8 as char)) {
+            else if char::is_ascii_whitespace(&(self.hack_char())) {
         
                 // skip white space
         
             }
-            else if self.char_hack as u8 as char == ';' {
+            else if self.hack_char() == ';' {
         
                 // skip comment to EOL
         
@@ -553,13 +703,13 @@ impl Spgn {
                         util::my_fatal(tmp.as_str());
                     }
                     
-                    if self.char_hack as u8 as char == '\n' {
+                    if self.hack_char() == '\n' {
                         break;
                     }
                 } //while (pgn->char_hack != '\n');
         
             } 
-            else if self.char_hack as u8 as char == '%' && self.char_column == 0 {
+            else if self.hack_char() == '%' && self.char_column == 0 {
         
                 // skip comment to EOL
         
@@ -575,13 +725,13 @@ impl Spgn {
                             self.game_nb);
                         util::my_fatal(tmp.as_str());
                     }
-                    if self.char_hack as u8 as char == '\n' {
+                    if self.hack_char() == '\n' {
                         break;
                     }
                 } //while (pgn->char_hack != '\n');
         
             }
-            else if self.char_hack as u8 as char == '{' {
+            else if self.hack_char() == '{' {
         
                 // skip comment to next '}'
         
@@ -597,7 +747,7 @@ impl Spgn {
                             self.game_nb);
                         util::my_fatal(tmp.as_str());
                     }
-                    if self.char_hack as u8 as char == '}' { break; }
+                    if self.hack_char() == '}' { break; }
                 } 
         
             } 
@@ -630,39 +780,24 @@ impl Spgn {
         
             assert_ne!(self.char_hack, CHAR_EOF);
             
-            if self.char_hack as u8 as char == '\n' {
+            if self.hack_char() == '\n' {
                 self.char_line += 1;
                 self.char_column = 0;
             } 
-            else if self.char_hack as u8 as char == '\t' {
+            else if self.hack_char() == '\t' {
                 self.char_column += TAB_SIZE - (self.char_column % TAB_SIZE);
             } else {
                 self.char_column += 1;
             }
         }
     
-        // read a new character
+        // read a new character, an utf-8 sequence of bytes
     
-        let mut buffer = [0; 1];	//only un char
-        
-        if let Some(file1) = self.file.as_ref() {
-            let file2 = file1.as_ref();
-            if file2.is_ok() {		        //file2 is a Result<>
-                match file2 {
-                    Ok(mut f) => {
-                        let _ = f.read_exact(&mut buffer);
-                        self.char_hack = buffer[0].into();
-                    },
-                    Err(_) => {},
-                }
-                // char_hack == 0 end of file
-            }
-            else if let Err(e) = file2 {
-                let tmp = format!("Error reading char in pgn_char_read : {}", e);
-                self.char_hack = CHAR_EOF;
-                util::my_fatal(tmp.as_str());
-            }
-        }
+        self.char_hack = match self.pgn_byte_read() {
+            Some(byte) if self.utf8 => self.pgn_decode_utf8(byte),
+            Some(byte) => decode_byte(byte, self.encoding.encoding),
+            None => 0,
+        };
 
         // end of file
         if self.char_hack == 0 {
@@ -682,6 +817,80 @@ impl Spgn {
     }
 
 
+    // pgn_byte_read()
+    // None at the end of file
+
+    fn pgn_byte_read(&mut self) -> Option<u8> {
+
+        if let Some(byte) = self.bytes_unread.pop() {
+            return Some(byte);
+        }
+
+        let mut buffer = [0; 1];	//only un byte
+        
+        if let Some(file1) = self.file.as_ref() {
+            let file2 = file1.as_ref();
+            match file2 {		        //file2 is a Result<>
+                Ok(mut f) => {
+                    if f.read_exact(&mut buffer).is_ok() {
+                        return Some(buffer[0]);
+                    }
+                },
+                Err(e) => {
+                    let tmp = format!("Error reading char in pgn_char_read : {}", e);
+                    util::my_fatal(tmp.as_str());
+                },
+            }
+        }
+        None
+    }
+
+
+    // pgn_decode_utf8()
+    // the code point of the character that begins with the byte;
+    // a byte that is not utf-8 is read with the fallback encoding
+
+    fn pgn_decode_utf8(&mut self, byte: u8) -> i32 {
+
+        let length: usize = match byte {
+            0x00..=0x7F => return byte.into(),
+            0xC2..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF4 => 4,
+            _ => return decode_byte(byte, self.encoding.fallback),
+        };
+
+        let mut bytes: Vec<u8> = vec![byte];
+        while bytes.len() < length {
+            match self.pgn_byte_read() {
+                Some(next) => {
+                    bytes.push(next);
+                    if next & 0xC0 != 0x80 { break; }
+                },
+                None => break,
+            }
+        }
+
+        if let Ok(text) = std::str::from_utf8(&bytes) {
+            return text.chars().next().unwrap() as i32;
+        }
+
+        // not utf-8, the next bytes will be read again
+        while bytes.len() > 1 {
+            self.bytes_unread.push(bytes.pop().unwrap());
+        }
+        decode_byte(byte, self.encoding.fallback)
+    }
+
+
+    // hack_char()
+    // the current character ('\0' at the end of file)
+
+    fn hack_char(&self) -> char {
+        std::char::from_u32(self.char_hack as u32).unwrap_or('\0')
+    }
+
+
     // pgn_char_unread()
 
     fn pgn_char_unread(&mut self) {
@@ -714,14 +923,14 @@ impl Spgn {
     
             self.pgn_token_read();
 
-            if self.token_type as u8 as char == '(' {
+            if self.token_type == '(' as i32 {
         
                 // open RAV
         
                 depth += 1;
         
             } 
-            else if self.token_type as u8 as char == ')' {
+            else if self.token_type == ')' as i32 {
         
                 // close RAV
 
@@ -757,7 +966,7 @@ impl Spgn {
                 if self.token_type == TokenT::TokenInteger as i32 {
                     loop {
                         self.pgn_token_read(); 
-                        if self.token_type as u8 as char != '.' { break; }
+                        if self.token_type != '.' as i32 { break; }
                     } //while (pgn->token_type == '.');
                 }
 
@@ -816,11 +1025,39 @@ impl Spgn {
 
 // functions out of struct methods
 
+// encoding_from_name()
+
+pub fn encoding_from_name(name: &str) -> Option<Encoding> {
+    match name.to_ascii_lowercase().as_str() {
+        "utf-8" | "utf8" => Some(Encoding::Utf8),
+        "latin-1" | "latin1" | "iso-8859-1" => Some(Encoding::Latin1),
+        "windows-1252" | "cp1252" => Some(Encoding::Windows1252),
+        _ => None,
+    }
+}
+
+
+// decode_byte()
+// the code point of a byte in a single byte encoding
+
+pub fn decode_byte(byte: u8, encoding: Encoding) -> i32 {
+    if encoding == Encoding::Windows1252 && (0x80..=0x9F).contains(&byte) {
+        let code = WINDOWS_1252[(byte - 0x80) as usize];
+        if code != 0 {
+            return code as i32;
+        }
+    }
+    byte.into()
+}
+
+
 // is_symbol_start()
 
-fn is_symbol_start(c: i32) -> bool {
+fn is_symbol_start(c: char) -> bool {
+    if san::is_figurine(c) { return true; }
+
     let test = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
-    let index = test.find(c as u8 as char);
+    let index = test.find(c);
     match index {
         Some(_) => {
             return true;
@@ -830,12 +1067,27 @@ fn is_symbol_start(c: i32) -> bool {
 }
 
 
+// is_ep_next()
+// the dots of an en passant suffix, as in exd6e.p.
+
+fn is_ep_next(c: char, token: &str) -> bool {
+
+    if c != '.' { return false; }
+
+    let prefix = token.trim_end_matches("e.p").trim_end_matches('e');
+    prefix.len() < token.len() && 
+        prefix.ends_with(|c: char| c.is_ascii_digit()) &&
+        (token.ends_with('e') || token.ends_with("e.p"))
+}
+
+
 // is_symbol_next()
 
-fn is_symbol_next(c: i32) -> bool {
+fn is_symbol_next(c: char) -> bool {
+    if san::is_figurine(c) { return true; }
 
     let test = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789_+#=:-/";
-    let index = test.find(c as u8 as char);
+    let index = test.find(c);
     match index {
         Some(_) => {
             return true;
@@ -0,0 +1,109 @@
+use super::{pgn, board, util, san, color};
+
+use std::time::{Instant};
+
+
+
+// check_pgn()
+// replays every game and reports those whose Result tag 
+// contradicts the final position (mate, stalemate, dead position)
+// the parser says if the moves must be strict SAN and their language
+
+pub fn check_pgn(pgn_file: &str, mut parser: san::SsanParser, encoding: pgn::SpgnEncoding) {
+
+    let mut pgn: pgn::Spgn = pgn::Spgn::new();
+    let mut board: board::Sboard = board::Sboard::new();
+    let mut san: String = String::new();
+
+    assert_ne!(pgn_file.len(), 0);
+
+    let now = Instant::now();
+
+    pgn.init_number_game(1);
+    pgn.encoding = encoding;
+    pgn.pgn_open(pgn_file);
+
+    let mut contador: i32 = 0;
+    let mut flagged: i32 = 0;
+
+    while pgn.pgn_next_game() {
+        let fen = pgn.get_fen();
+        if fen.is_empty() {
+            board.set_fen(util::START_FEN);
+        }
+        else if !board.set_fen(fen.as_str()) {
+            println!("game {}: invalid FEN tag \"{}\"", pgn.game_nb, fen);
+            flagged += 1;
+            skip_moves(&mut pgn, &mut san);
+            contador += 1;
+            pgn.game_nb += 1;
+            continue;
+        }
+
+        let mut legal = true;
+        while pgn.pgn_next_move(&mut san, 256) {
+            if !legal { continue; }
+
+            match parser.parse(san.as_str(), &mut board) {
+                Ok(mut m) => {
+                    board.make_move(&mut m);
+                },
+                Err(e) => {
+                    println!("game {}: {} \"{}\" at line {}, column {}",
+                        pgn.game_nb, e, san, pgn.move_line, pgn.move_column);
+                    flagged += 1;
+                    legal = false;
+                },
+            }
+        }
+
+        if legal {
+            let result = pgn.get_result();
+            let expected = expected_result(&mut board);
+            if let Some((status, expected)) = expected {
+                if result.trim() != expected {
+                    println!("game {}: result \"{}\" but the final position is {:?} ({})",
+                        pgn.game_nb, result.trim(), status, expected);
+                    flagged += 1;
+                }
+            }
+        }
+
+        contador += 1;
+        pgn.game_nb += 1;
+    }
+
+    println!("total checked games: {}", contador);
+    println!("games with errors: {}", flagged);
+    println!("Time used: {}", now.elapsed().as_secs());
+}
+
+
+// expected_result()
+// the only result compatible with a final position, if it is forced
+
+fn expected_result(board: &mut board::Sboard) -> Option<(board::GameStatus, &'static str)> {
+
+    let status = board.status();
+    match status {
+        board::GameStatus::Checkmate => {
+            if board.side == color::WHITE {
+                Some((status, "0-1"))
+            }
+            else {
+                Some((status, "1-0"))
+            }
+        },
+        board::GameStatus::Stalemate |
+        board::GameStatus::InsufficientMaterial => Some((status, "1/2-1/2")),
+        // repetition and fifty moves must be claimed, any result is possible
+        _ => None,
+    }
+}
+
+
+// skip_moves()
+
+fn skip_moves(pgn: &mut pgn::Spgn, san: &mut String) {
+    while pgn.pgn_next_move(san, 256) {}
+}
@@ -1,109 +1,417 @@
 use super::board as b;
 use super::color;
 use super::square;
-//use super::piece;
+use super::piece;
 use super::moves as mv;
 
 
 
-// move_from_san()
+// errors of the SAN resolution
+
+#[derive(Debug, PartialEq)]
+pub enum SanError {
+    Malformed,                  // it is not a SAN move
+    NoSuchMove,                 // no legal move matches
+    Ambiguous(Vec<String>),     // several legal moves match, in uci notation
+}
 
-pub fn move_from_san(string_: String, mut board: &mut b::Sboard) -> Option<mv::Smove> {
+impl std::fmt::Display for SanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SanError::Malformed => write!(f, "malformed move"),
+            SanError::NoSuchMove => write!(f, "illegal move"),
+            SanError::Ambiguous(candidates) => 
+                write!(f, "ambiguous move ({})", candidates.join(", ")),
+        }
+    }
+}
 
-    //let mut s: String = String::with_capacity(16);
 
-    assert!(string_.len() > 0);
 
-    //s = String::from("");
+// the SAN regex, 8 tokens
+// lenient: "-" or ":" between the squares, "=" omitted, lowercase promotions
+// (check signs and annotations are removed before)
 
-    let move_ = get_move_from_san(string_, &mut board);
+const LENIENT_PATTERN: &str = r"^([PNBRQK])?([a-h])?([1-8])?(x|-|:)?([a-h][1-8])(=?[qrbnQRBN])?(\+|#)?$";
+const STRICT_PATTERN: &str  = r"^([NBRQK])?([a-h])?([1-8])?(x)?([a-h][1-8])(=[QRBN])?(\+|#)?$";
+const UCI_PATTERN: &str     = r"^([a-h][1-8])([a-h][1-8])([qrbnQRBN])?$";
 
-    return move_;
+
+
+// the letters of the pieces in every language, in the order of the 
+// piece module: pawn, knight, bishop, rook, queen, king
+
+pub struct SpieceLetters {
+    pub name: &'static str,
+    pub letters: [char; 6],
 }
 
-// get_move_from_san()
+pub const PIECE_LETTERS: [SpieceLetters; 6] = [
+    SpieceLetters { name: "en", letters: ['P', 'N', 'B', 'R', 'Q', 'K'] },
+    SpieceLetters { name: "es", letters: ['P', 'C', 'A', 'T', 'D', 'R'] },
+    SpieceLetters { name: "de", letters: ['B', 'S', 'L', 'T', 'D', 'K'] },
+    SpieceLetters { name: "fr", letters: ['P', 'C', 'F', 'T', 'D', 'R'] },
+    SpieceLetters { name: "it", letters: ['P', 'C', 'A', 'T', 'D', 'R'] },
+    SpieceLetters { name: "figurine", letters: ['♙', '♘', '♗', '♖', '♕', '♔'] },
+];
 
-fn get_move_from_san(san: String, mut board: &mut b::Sboard) -> Option<mv::Smove> {
-    use regex::Regex;
+// the figurines of both colors are read in every language
+const BLACK_FIGURINES: [char; 6] = ['♟', '♞', '♝', '♜', '♛', '♚'];
 
-    // we shall have 8 tokens
-    let pattern: &str = r"^([PNBRQK])?([a-h])?([1-8])?(x|-)?([a-h][1-8])(=?[qrbnQRBN])?(\+|#)?$";
-    
-    let mut tokens: [&str; 8] = ["", "", "", "", "", "", "", ""];
-    let pieces: [&str; 6] = ["", "N", "B", "R", "Q", "K"];
-    let promotions: [char; 9] = [' ', ' ', ' ', ' ', ' ', 'Q', 'R', 'B', 'N'];
-    let mut uci_str: String = String::from("");
-    let from: &str;
-    let to: &str;
-    let mut moves: Vec<mv::Smove> = Vec::new();
-    
-    
 
-    // FIRST: test if the move is castle
+// piece_letters()
+// the letters of a language by its name
 
-    match san.as_str() {
-        "O-O" | "O-O+" => {
-            if board.side == color::WHITE {
-                from = "e1";    //"e1g1".to_string(); 
-                to = "g1";
-            }
-            else {
-                //uci_str = "e8g8".to_string(); 
-                from = "e8";
-                to = "g8";
+pub fn piece_letters(name: &str) -> Option<&'static SpieceLetters> {
+    PIECE_LETTERS.iter().find(|l| l.name == name)
+}
+
+
+// is_figurine()
+
+pub fn is_figurine(c: char) -> bool {
+    PIECE_LETTERS[PIECE_LETTERS.len() - 1].letters.contains(&c) || BLACK_FIGURINES.contains(&c)
+}
+
+
+
+/* How the moves are read: strict SAN for validation, or 
+*  lenient with the dialects found in real PGNs: 0-0, e8Q, exd6e.p.,
+*  Ng1-f3, e2e4, Nf3!?, null moves -- and Z0 
+*  The pieces can be in another language (Cf3 in spanish) or figurines, and 
+*  with auto the language is the one of the moves read until now */
+#[derive(Clone)]
+pub struct SsanParser {
+    pub strict: bool,
+    pub letters: &'static SpieceLetters,
+    pub auto: bool,
+}
+
+impl SsanParser {
+    pub fn new() -> Self {
+        SsanParser {
+            strict: false,
+            letters: &PIECE_LETTERS[0],
+            auto: false,
+        }
+    }
+
+    pub fn strict() -> Self {
+        SsanParser {
+            strict: true,
+            letters: &PIECE_LETTERS[0],
+            auto: false,
+        }
+    }
+
+
+    // set_pieces()
+    // the language of the pieces, or "auto"; false if it is unknown
+
+    pub fn set_pieces(&mut self, name: &str) -> bool {
+        if name == "auto" {
+            self.auto = true;
+            return true;
+        }
+        match piece_letters(name) {
+            Some(letters) => {
+                self.letters = letters;
+                self.auto = false;
+                true
+            },
+            None => false,
+        }
+    }
+
+
+    // parse()
+    // with auto the languages are tried until one of them gives the move,
+    // and if the move has pieces that language is kept for the next ones
+
+    pub fn parse(&mut self, san: &str, board: &mut b::Sboard) -> Result<mv::Smove, SanError> {
+
+        let first = self.parse_in(san, board, self.letters);
+        if !self.auto || first.is_ok() {
+            return first;
+        }
+
+        for letters in PIECE_LETTERS.iter() {
+            if letters.name == self.letters.name {
+                continue;
             }
-            
-            //res = moveUCI(board, uciStr)
-            //return
-            let res = get_move_from_uci(from, to, &mut board);
-            return res;
-        },
-        "O-O-O" | "O-O-O+" => {
-            if board.side == color::WHITE {
-                //uci_str = "e1c1".to_string();
-                from = "e1";
-                to = "c1";
+            if let Ok(m) = self.parse_in(san, board, letters) {
+                if san.chars().any(|c| letters.letters[1..].contains(&c)) {
+                    self.letters = letters;
+                }
+                return Ok(m);
             }
-            else {
-                //uci_str = "e8c8".to_string();
-                from = "e8";
-                to = "c8";
+        }
+        first
+    }
+
+
+    // parse_in()
+    // the move with the pieces of a language
+    
+    fn parse_in(&self, san: &str, board: &mut b::Sboard, letters: &SpieceLetters) -> Result<mv::Smove, SanError> {
+
+        let english = to_english(san, letters);
+        self.parse_english(english.as_str(), board)
+    }
+
+
+    // parse_english()
+    // the legal move of the SAN, or why there is not only one
+    // a null move is returned as a move of type MOVE_TYPE_NONE
+
+    fn parse_english(&self, san: &str, board: &mut b::Sboard) -> Result<mv::Smove, SanError> {
+
+        if self.strict {
+            return match san {
+                "O-O" | "O-O+" | "O-O#" => get_castle(board, false),
+                "O-O-O" | "O-O-O+" | "O-O-O#" => get_castle(board, true),
+                _ => get_move_from_san(san, board, true),
+            };
+        }
+
+        let mut s: &str = san.trim();
+
+        // null moves
+        if s == "--" || s == "Z0" || s == "0000" {
+            let mut null_move = mv::Smove::new();
+            null_move.hash = board.hash_key;
+            return Ok(null_move);
+        }
+
+        // annotations and check signs glued to the move
+        s = s.trim_end_matches(&['!', '?', '+', '#'][..]);
+
+        // en passant suffix
+        for suffix in ["e.p.", "ep"].iter() {
+            if s.ends_with(suffix) {
+                let rest = &s[..s.len() - suffix.len()];
+                if rest.ends_with(|c: char| square::char_is_rank(c)) {
+                    s = rest;
+                }
             }
-            
-            let res = get_move_from_uci(from, to, &mut board);
+        }
+
+        // castles with zeros
+        match s.replace('0', "O").as_str() {
+            "O-O" => return get_castle(board, false),
+            "O-O-O" => return get_castle(board, true),
+            _ => (),
+        }
+
+        if let Some(res) = get_move_from_uci(s, board) {
             return res;
         }
-        _ => (),
+
+        get_move_from_san(s, board, false)
     }
+}
+
+
+
+// to_english()
+// the letters of the pieces (and the figurines) to english, 
+// the rest of the move does not change
+
+fn to_english(san: &str, letters: &SpieceLetters) -> String {
+    let english = &PIECE_LETTERS[0].letters;
+    let figurines = &PIECE_LETTERS[PIECE_LETTERS.len() - 1].letters;
+
+    san.chars().map(|c| {
+        if let Some(p) = figurines.iter().position(|&f| f == c) {
+            english[p]
+        }
+        else if let Some(p) = BLACK_FIGURINES.iter().position(|&f| f == c) {
+            english[p]
+        }
+        else if c.is_ascii_uppercase() && c != 'O' && c != 'Z' {
+            match letters.letters.iter().position(|&l| l == c) {
+                Some(p) => english[p],
+                None => '?',    // it is not a piece of this language
+            }
+        }
+        else {
+            c
+        }
+    }).collect()
+}
 
-    // SECOND: begin with regex of SAN move
 
-    let re = Regex::new(pattern).unwrap();
+// move_to_san()
+// the SAN of a legal move of the position, with the pieces of a language
 
-    assert!(re.is_match(&san));
+pub fn move_to_san(m: &mv::Smove, board: &mut b::Sboard, letters: &SpieceLetters) -> String {
 
-    for caps in re.captures_iter(&san) {
-        tokens[0] = caps.get(0).unwrap().as_str();
-        if caps.get(1).is_some() {
-            tokens[1] = caps.get(1).unwrap().as_str();
+    let mut san: String = String::new();
+
+    if m.tipe == mv::MOVE_TYPE_NONE {
+        return String::from("--");
+    }
+
+    if m.tipe == mv::MOVE_TYPE_CASTLE {
+        if m.dest < m.from {
+            san.push_str("O-O-O");
         }
-        if caps.get(2).is_some() {
-            tokens[2] = caps.get(2).unwrap().as_str();
+        else {
+            san.push_str("O-O");
         }
-        if caps.get(3).is_some() {
-            tokens[3] = caps.get(3).unwrap().as_str();
+    }
+    else {
+        let piece_moved = board.piece[m.from];
+        let capture = (board.piece[m.dest] != square::EMPTY && 
+            board.piece[m.dest] != square::EPS_SQUARE) || m.tipe == mv::MOVE_TYPE_EPS;
+        let uci: String = m.to_uci();
+
+        if piece_moved == piece::PAWN {
+            if capture {
+                san.push_str(&uci[0..1]);
+            }
         }
-        if caps.get(4).is_some() {
-            tokens[4] = caps.get(4).unwrap().as_str();
+        else {
+            san.push(letters.letters[piece_moved]);
+
+            // the other pieces of the same type that can go to the square
+            let others = candidates(board, |o, board| {
+                o.dest == m.dest && o.from != m.from && board.piece[o.from] == piece_moved
+            });
+            if !others.is_empty() {
+                let same_file = others.iter().any(|o| o.to_uci()[0..1] == uci[0..1]);
+                let same_rank = others.iter().any(|o| o.to_uci()[1..2] == uci[1..2]);
+                if !same_file {
+                    san.push_str(&uci[0..1]);
+                }
+                else if !same_rank {
+                    san.push_str(&uci[1..2]);
+                }
+                else {
+                    san.push_str(&uci[0..2]);
+                }
+            }
         }
-        if caps.get(5).is_some() {
-            tokens[5] = caps.get(5).unwrap().as_str();
+
+        if capture {
+            san.push('x');
         }
-        if caps.get(6).is_some() {
-            tokens[6] =caps.get(6).unwrap().as_str();
+        san.push_str(&uci[2..4]);
+
+        if m.tipe >= mv::MOVE_TYPE_PROMOTION_TO_QUEEN {
+            san.push('=');
+            san.push(letters.letters[piece::QUEEN + mv::MOVE_TYPE_PROMOTION_TO_QUEEN - m.tipe]);
+        }
+    }
+
+    // check or mate
+    let mut mov = m.clone();
+    if board.make_move(&mut mov).is_some() && board.in_check() {
+        if !board.has_legal_moves() {
+            san.push('#');
+        }
+        else {
+            san.push('+');
+        }
+    }
+    board.undo_move(&mut mov);
+
+    san
+}
+
+
+// move_from_san()
+
+pub fn move_from_san(string_: String, board: &mut b::Sboard) -> Option<mv::Smove> {
+
+    resolve_san(string_.as_str(), board).ok()
+}
+
+
+// resolve_san()
+// the move with the lenient parser
+
+pub fn resolve_san(san: &str, board: &mut b::Sboard) -> Result<mv::Smove, SanError> {
+
+    assert!(!san.is_empty());
+
+    SsanParser::new().parse(san, board)
+}
+
+
+// get_castle()
+
+fn get_castle(board: &mut b::Sboard, long: bool) -> Result<mv::Smove, SanError> {
+
+    let from: usize;
+    let to: usize;
+
+    if board.side == color::WHITE {
+        from = square::E1;
+        to = if long { square::C1 } else { square::G1 };
+    }
+    else {
+        from = square::E8;
+        to = if long { square::C8 } else { square::G8 };
+    }
+    only_one(candidates(board, |m, board| {
+        m.from == from && m.dest == to && board.piece[m.from] == piece::KING
+    }))
+}
+
+
+// get_move_from_uci()
+// None if it is not uci; castles can be e1g1 or e1h1 (polyglot)
+
+fn get_move_from_uci(uci: &str, board: &mut b::Sboard) -> Option<Result<mv::Smove, SanError>> {
+    use regex::Regex;
+
+    let re = Regex::new(UCI_PATTERN).unwrap();
+    let caps = re.captures(uci)?;
+
+    let from: usize = square::ALGEBRA_SQUARE.iter().position(|&r| r == &caps[1]).unwrap();
+    let mut dest: usize = square::ALGEBRA_SQUARE.iter().position(|&r| r == &caps[2]).unwrap();
+    let promoted: usize = match caps.get(3) {
+        Some(prom) => promotion_type(prom.as_str().chars().next().unwrap()),
+        None => mv::MOVE_TYPE_NONE,
+    };
+
+    // the king "captures" its own rook, from its first square only
+    if board.piece[from] == piece::KING && board.piece[dest] == piece::ROOK &&
+            board.color[dest] == board.color[from] {
+        match (from, dest) {
+            (square::E1, square::H1) | (square::E8, square::H8) => dest = from + 2,
+            (square::E1, square::A1) | (square::E8, square::A8) => dest = from - 2,
+            _ => {},
         }
-        if caps.get(7).is_some() {
-            tokens[7] = caps.get(7).unwrap().as_str();
+    }
+
+    Some(only_one(candidates(board, |m, _board| {
+        m.from == from && m.dest == dest && same_promotion(m, promoted)
+    })))
+}
+
+
+// get_move_from_san()
+
+fn get_move_from_san(san: &str, board: &mut b::Sboard, strict: bool) -> Result<mv::Smove, SanError> {
+    use regex::Regex;
+
+    // we shall have 8 tokens
+    let mut tokens: [&str; 8] = ["", "", "", "", "", "", "", ""];
+    let pieces: [&str; 6] = ["", "N", "B", "R", "Q", "K"];
+
+    // FIRST: begin with regex of SAN move
+
+    let re = Regex::new(if strict { STRICT_PATTERN } else { LENIENT_PATTERN }).unwrap();
+
+    let caps = match re.captures(san) {
+        Some(caps) => caps,
+        None => return Err(SanError::Malformed),
+    };
+    for (i, token) in tokens.iter_mut().enumerate() {
+        if let Some(group) = caps.get(i) {
+            *token = group.as_str();
         }
     }
     /*
@@ -118,126 +426,99 @@ fn get_move_from_san(san: String, mut board: &mut b::Sboard) -> Option<mv::Smove
     ! tokens(7) = +
     */
 
-    // THIRD, test if origin square is complete
-
-    if tokens[2].len() > 0 && tokens[3].len() > 0 {
-        //uciStr = tokens(2)(1:1) // tokens(3)(1:1) // tokens(5)(1:2)
-        uci_str.clear();
-        uci_str.push_str(tokens[2]);
-        uci_str.push_str(tokens[3]);
-        //uci_str.push_str(tokens[5]);
+    // SECOND, what the move must match
 
-        if tokens[6].len() > 0 {
-            // convert to lower case promotion piece :Q, B, ...
-            let prom: char = tokens[6].chars().nth(1).unwrap();
-            uci_str.push(prom.to_lowercase().nth(0).unwrap());
-        }
-        
-        //res = moveUCI(board, uciStr)
-        //return
-        let res = get_move_from_uci(uci_str.trim(), tokens[5].trim(), &mut board);
-        return res;
+    let piece_san: usize = match pieces.iter().position(|&r| r == tokens[1].trim_start_matches('P')) {
+        Some(p) => p,       // PAWN WILL BE ZERO
+        None => return Err(SanError::Malformed),
+    };
+    // in SAN a pawn only has the file of origin, and only if it captures
+    if strict && piece_san == piece::PAWN && 
+            (!tokens[3].is_empty() || tokens[2].is_empty() != tokens[4].is_empty()) {
+        return Err(SanError::Malformed);
     }
 
-    // FOURTH, the other moves
+    let dest: usize = square::ALGEBRA_SQUARE.iter().position(|&r| r == tokens[5]).unwrap();
+    let from_file: Option<char> = tokens[2].chars().next();
+    let from_rank: Option<char> = tokens[3].chars().next();
+
+    // some notations ommits the '='
+    let promoted: usize = match tokens[6].trim_start_matches('=').chars().next() {
+        Some(prom) => promotion_type(prom),
+        None => mv::MOVE_TYPE_NONE,
+    };
+
+    // THIRD, the legal moves that match
+
+    only_one(candidates(board, |m, board| {
+        let uci: String = m.to_uci();
+        let mut chars = uci.chars();
+        let m_file = chars.next();
+        let m_rank = chars.next();
+
+        m.dest == dest &&
+        board.piece[m.from] == piece_san &&
+        (from_file.is_none() || from_file == m_file) &&
+        (from_rank.is_none() || from_rank == m_rank) &&
+        same_promotion(m, promoted)
+    }))
+}
+
 
-    // variables to find the correct move
-    //let str_san_length = san.trim().len();
-    let piece_san: usize = pieces.iter().position(|&r| r == tokens[1].trim()).unwrap();    // PAWN WILL BE ZERO
-    
-    let num_moves: i32 = board.gen_moves(&mut moves);
-    //let mut move_num = 0; // 0 = NONE_MOVE
-
-    for j in 0..num_moves {
-        //tmp_move = moves[j as usize].clone();
-        let mvs_from: usize = moves[j as usize].from;
-        let mvs_to: usize   = moves[j as usize].dest;
-
-        let mut end_str: String = String::from("");
-        let tmp_str: String     = mv::get_uci_format(moves[j as usize].encoded_move);
-
-        // first examine the destination square
-        if square::ALGEBRA_SQUARE[mvs_to] == tokens[5] {
-            
-            // same piece
-            if board.piece[mvs_from] == piece_san {
-                
-                // promotion?
-                if tokens[6].len() > 0 {
-                    let mut prom: char;
-                    // some notations ommits the '='
-                    if tokens[6].chars().nth(0).unwrap() == '=' {
-                        prom = tokens[6].chars().nth(1).unwrap();
-                    }
-                    else { prom = tokens[6].chars().nth(0).unwrap(); }
-
-                    let promoted: usize = promotions.iter().position(|&r| r == prom).unwrap();
-                    //convert to lowercase vias ascii codes
-                    prom = ((prom as u8) + 32) as char;
-                    if promoted == moves[j as usize].tipe {
-                        end_str.push_str(tokens[5].trim());
-                        end_str.push(prom);
-                    }
-                }
-                else {
-                    end_str.push_str(tokens[5].trim());
-                }
+// promotion_type()
 
-                // disambiguate?
-
-                if tokens[2].trim().len() > 0 || tokens[3].trim().len() > 0 {
-
-                    if tokens[2].trim().len() > 0 && tokens[3].trim().len() == 0 {
-                        if tmp_str[0..1].trim() == tokens[2].trim() {
-                            if tmp_str[2..].trim() == end_str.trim() {
-                                return Some(moves[j as usize].clone());
-                            }
-                        }
-                    }
-                    if tokens[3].trim().len() > 0 && tokens[2].trim().len() == 0 {
-                        if &tmp_str[1..2] == tokens[3].trim() {
-                            if tmp_str[2..].trim() == end_str.trim() {
-                                return Some(moves[j as usize].clone());
-                            }
-                        }
-                    }
+fn promotion_type(prom: char) -> usize {
+    let promotions: [char; 9] = [' ', ' ', ' ', ' ', ' ', 'Q', 'R', 'B', 'N'];
 
-                }
+    let prom = prom.to_ascii_uppercase();
+    promotions.iter().position(|&r| r == prom).unwrap()
+}
 
-                // no disambiguation
-                if tokens[2].len() == 0 && tokens[3].len() == 0 {
 
-                    if tmp_str[2..].trim() == end_str.trim() {
-                        return Some(moves[j as usize].clone());
-                    }
-                }
-                
-            }
-        }
+// same_promotion()
+// a promotion needs the piece, and the other moves have not it
+
+fn same_promotion(m: &mv::Smove, promoted: usize) -> bool {
+    if m.tipe >= mv::MOVE_TYPE_PROMOTION_TO_QUEEN {
+        m.tipe == promoted
+    }
+    else {
+        promoted == mv::MOVE_TYPE_NONE
     }
-    None
 }
 
 
-fn get_move_from_uci(v_from: &str, v_to: &str, board: &mut b::Sboard) -> Option<mv::Smove> {
+// candidates()
+// the legal moves of the position that satisfy the condition
 
-    let mut moves: Vec<mv::Smove> = Vec::new();
+fn candidates<F>(board: &mut b::Sboard, condition: F) -> Vec<mv::Smove>
+        where F: Fn(&mv::Smove, &b::Sboard) -> bool {
 
-    let from: usize = square::ALGEBRA_SQUARE.iter().position(|&r| r == v_from).unwrap();
-    let to: usize = square::ALGEBRA_SQUARE.iter().position(|&r| r == v_to).unwrap();
-
-    let num_moves: i32 = board.gen_moves(&mut moves);
+    let mut moves: Vec<mv::Smove> = Vec::new();
+    let mut found: Vec<mv::Smove> = Vec::new();
 
-    for j in 0..num_moves {
-        //tmp_move = moves[j as usize].clone();
-        //let mvs_from: usize = moves[j as usize].from;
-        //let mvs_to: usize   = moves[j as usize].dest;
+    board.gen_moves(&mut moves);
 
-        if from == moves[j as usize].from && 
-                to == moves[j as usize].dest {
-            return Some(moves[j as usize].clone());
+    for m in moves.iter_mut() {
+        if !condition(m, board) {
+            continue;
+        }
+        let legal = board.make_move(m).is_some();
+        board.undo_move(m);
+        if legal {
+            found.push(m.clone());
         }
     }
+    found
+}
+
 
-    None
+// only_one()
+
+fn only_one(mut found: Vec<mv::Smove>) -> Result<mv::Smove, SanError> {
+    match found.len() {
+        0 => Err(SanError::NoSuchMove),
+        1 => Ok(found.remove(0)),
+        _ => Err(SanError::Ambiguous(found.iter().map(|m| m.to_uci()).collect())),
+    }
 }
\ No newline at end of file
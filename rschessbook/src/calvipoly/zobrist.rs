@@ -328,8 +328,8 @@ pub fn hash_key(board: &mut b::Sboard) -> u64 {
         }
     }
 
-    // castle flags
-    let flags = board.get_board_flags();
+    // castle flags (the rights, not only the king and rooks in place)
+    let flags = board.castle_rights;
     
     // comprobar si esta disponible enroque corto del blanco
     let resul = flags & moves::Enroques::WKCA as usize;